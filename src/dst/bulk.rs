@@ -0,0 +1,157 @@
+//! An incremental reader for StatBank's semicolon separated `BULK` format.
+//!
+//! Rows are parsed one at a time straight off the response, so extracts with millions of
+//! cells never need to be held in memory as a whole.
+
+use std::io::Read;
+
+use super::models::metadata::Variable;
+use super::DataPoint;
+
+pub struct Reader<R: Read> {
+    records: csv::StringRecordsIntoIter<R>,
+    /// The variable id for each column but the last.
+    columns: Vec<String>,
+}
+
+/// The tag for the content code column, which StatBank adds even when the metadata leaves it out.
+pub const CONTENTS_CODE: &str = "ContentsCode";
+
+impl<R: Read> Reader<R> {
+    /// Reads the header and matches its columns to the table's variables, ignoring case.
+    ///
+    /// Content codes are kept as a tag, so different measures are never added up.
+    pub fn new(input: R, variables: &[Variable]) -> Result<Reader<R>, failure::Error> {
+        let mut reader = csv::ReaderBuilder::new().delimiter(b';').from_reader(input);
+        let header = reader.headers()?.clone();
+        if header.len() < 2 {
+            failure::bail!(
+                "expected variable columns and a value column, got {:?}",
+                header
+            );
+        }
+
+        let columns = header
+            .iter()
+            .take(header.len() - 1)
+            .map(|name| {
+                let name = name.trim_start_matches('\u{feff}');
+                variables
+                    .iter()
+                    .map(|v| v.id.as_str())
+                    .chain(Some(CONTENTS_CODE))
+                    .find(|id| id.to_uppercase() == name.to_uppercase())
+                    .map(str::to_string)
+                    .ok_or_else(|| failure::format_err!("unknown column {}", name))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Reader {
+            records: reader.into_records(),
+            columns,
+        })
+    }
+
+    /// The tags of every point, in the order of the columns.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+}
+
+/// Values may have a decimal comma; missing values (`..`) are `None` like nulls in JSONSTAT.
+fn parse_value(value: &str) -> Result<Option<f64>, failure::Error> {
+    let value = value.trim();
+    if value.is_empty() || value.chars().all(|c| c == '.' || c == '-') {
+        return Ok(None);
+    }
+    value
+        .replace(',', ".")
+        .parse::<f64>()
+        .map(Some)
+        .map_err(|_| failure::format_err!("invalid value: {}", value))
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<DataPoint, failure::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e.into())),
+        };
+        if record.len() != self.columns.len() + 1 {
+            return Some(Err(failure::format_err!(
+                "expected {} columns, got {:?}",
+                self.columns.len() + 1,
+                record
+            )));
+        }
+
+        let tags = self
+            .columns
+            .iter()
+            .zip(record.iter())
+            .map(|(id, text)| (id.clone(), text.to_string()))
+            .collect();
+        Some(parse_value(&record[self.columns.len()]).map(|value| DataPoint { value, tags }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::models::data::DatasetContainer;
+    use super::super::models::metadata::Metadata;
+    use super::super::DataPoint;
+    use super::Reader;
+
+    fn variables() -> Vec<super::Variable> {
+        serde_json::from_str::<Metadata>(include_str!(
+            "../../test/data/dst.metadata.response.bil51.json"
+        ))
+        .unwrap()
+        .variables
+    }
+
+    #[test]
+    fn test_same_as_jsonstat() {
+        let bulk = include_str!("../../test/data/dst.data.response.bil51.bulk.csv");
+        let points = Reader::new(bulk.as_bytes(), &variables())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let response: DatasetContainer = serde_json::from_str(include_str!(
+            "../../test/data/dst.data.response.bil51.large.json"
+        ))
+        .unwrap();
+        let expected = DataPoint::from_dimensions_and_data(
+            &response.dataset.dimension,
+            &response.dataset.value,
+        )
+        .unwrap();
+
+        assert_eq!(points, expected);
+    }
+
+    #[test]
+    fn test_values_and_errors() {
+        let input = "CONTENTSCODE;EJER;TID;INDHOLD\nBIL51;Erhvervene;2020M01;..\nBIL51;Erhvervene;2020M02;12,6\n";
+        let points: Vec<DataPoint> = Reader::new(input.as_bytes(), &variables())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].value, None);
+        assert_eq!(points[1].value, Some(12.6));
+        assert_eq!(points[1].tags["Tid"], "2020M02");
+        assert_eq!(points[1].tags["ContentsCode"], "BIL51");
+
+        let input = "EJER;FARVE;TID;INDHOLD\nErhvervene;Rød;2020M01;1\n";
+        assert!(Reader::new(input.as_bytes(), &variables()).is_err());
+
+        let input = "EJER;TID;INDHOLD\nErhvervene;2020M01;mange\nErhvervene;2020M02\n";
+        let mut reader = Reader::new(input.as_bytes(), &variables()).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().unwrap().is_err());
+    }
+}
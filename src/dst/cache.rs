@@ -0,0 +1,124 @@
+//! A disk cache of StatBank responses, so a build can publish the last known numbers
+//! rather than fail when the API is unreachable.
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: &Path) -> Cache {
+        Cache { dir: dir.into() }
+    }
+
+    /// Responses are keyed by the URL and the request body.
+    fn path(&self, url: &str, request: &[u8]) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(url.as_bytes());
+        hasher.update([0]);
+        hasher.update(request);
+        let key: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.dir.join(format!("{}.json", key))
+    }
+
+    pub fn store(&self, url: &str, request: &[u8], response: &[u8]) -> Result<(), failure::Error> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(url, request);
+        // Written aside and renamed, so an interrupted build never leaves half a response.
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, response)?;
+        std::fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    /// The last stored response and when it was stored.
+    pub fn load(&self, url: &str, request: &[u8]) -> Option<(DateTime<Utc>, Vec<u8>)> {
+        let path = self.path(url, request);
+        let fetched = std::fs::metadata(&path).ok()?.modified().ok()?;
+        Some((fetched.into(), std::fs::read(path).ok()?))
+    }
+}
+
+/// Stores a successful response, or falls back to the stored one when the request failed.
+/// Failing to store a response is only a warning, as the response itself is still good.
+///
+/// Returns the response and, for a fallback, when it was originally fetched.
+pub fn or_stale(
+    cache: Option<&Cache>,
+    url: &str,
+    request: &[u8],
+    result: Result<Vec<u8>, failure::Error>,
+) -> Result<(Vec<u8>, Option<DateTime<Utc>>), failure::Error> {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => return result.map(|response| (response, None)),
+    };
+    match result {
+        Ok(response) => {
+            if let Err(e) = cache.store(url, request, &response) {
+                eprintln!("{}: could not cache the response: {}", url, e);
+            }
+            Ok((response, None))
+        }
+        Err(e) => match cache.load(url, request) {
+            Some((fetched, response)) => {
+                eprintln!("{}: using the response from {}: {}", url, fetched, e);
+                Ok((response, Some(fetched)))
+            }
+            None => Err(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{or_stale, Cache};
+
+    #[test]
+    fn test_or_stale() {
+        let dir = std::env::temp_dir().join(format!("klima.cache.{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = Cache::new(&dir);
+        let url = "https://api.statbank.dk/v1/data";
+        let failure = || Err(failure::format_err!("unreachable"));
+
+        assert!(or_stale(Some(&cache), url, b"BIL51", failure()).is_err());
+        assert!(or_stale(None, url, b"BIL51", failure()).is_err());
+
+        let (response, stale) =
+            or_stale(Some(&cache), url, b"BIL51", Ok(b"fresh".to_vec())).unwrap();
+        assert_eq!((response.as_slice(), stale), (&b"fresh"[..], None));
+
+        let (response, stale) = or_stale(Some(&cache), url, b"BIL51", failure()).unwrap();
+        assert_eq!(response, b"fresh");
+        assert!(stale.is_some());
+
+        // Other requests to the same URL are cached separately.
+        assert!(or_stale(Some(&cache), url, b"BIL54", failure()).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_store_failure() {
+        // A file where the cache directory should be makes every store fail.
+        let dir = std::env::temp_dir().join(format!("klima.cache.file.{}", std::process::id()));
+        std::fs::write(&dir, b"").unwrap();
+        let cache = Cache::new(&dir);
+        let url = "https://api.statbank.dk/v1/data";
+
+        let (response, stale) =
+            or_stale(Some(&cache), url, b"BIL51", Ok(b"fresh".to_vec())).unwrap();
+        assert_eq!((response.as_slice(), stale), (&b"fresh"[..], None));
+        assert!(cache.load(url, b"BIL51").is_none());
+
+        std::fs::remove_file(dir).unwrap();
+    }
+}
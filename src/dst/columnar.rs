@@ -0,0 +1,206 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Date32Array, Float64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use chrono::NaiveDate;
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+
+use super::DataPoint;
+
+/// Rows per record batch and Parquet row group, which bounds the memory used for a selection.
+const BATCH_ROWS: usize = 64 * 1024;
+
+fn tag<'a>(point: &'a DataPoint, dimension: &str) -> Result<&'a str, failure::Error> {
+    point
+        .tags
+        .get(dimension)
+        .map(String::as_str)
+        .ok_or_else(|| failure::format_err!("data point has no {}", dimension))
+}
+
+/// Converts data points to one string column per dimension, followed by a date and a value
+/// column. Missing values are nulls.
+pub fn to_record_batch(
+    dimensions: &[&str],
+    time_id: &str,
+    data: &[DataPoint],
+) -> Result<RecordBatch, failure::Error> {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+
+    let mut fields = vec![];
+    let mut columns: Vec<ArrayRef> = vec![];
+    for dimension in dimensions.iter().filter(|d| **d != time_id) {
+        fields.push(Field::new(*dimension, DataType::Utf8, false));
+        let tags = data
+            .iter()
+            .map(|p| tag(p, dimension))
+            .collect::<Result<Vec<_>, _>>()?;
+        columns.push(Arc::new(StringArray::from(tags)));
+    }
+
+    fields.push(Field::new(time_id, DataType::Date32, false));
+    let days = data
+        .iter()
+        .map(|p| {
+            let date = DataPoint::parse_time(tag(p, time_id)?)?;
+            Ok((date - epoch).num_days() as i32)
+        })
+        .collect::<Result<Vec<_>, failure::Error>>()?;
+    columns.push(Arc::new(Date32Array::from(days)));
+
+    fields.push(Field::new("value", DataType::Float64, true));
+    columns.push(Arc::new(Float64Array::from(
+        data.iter().map(|p| p.value).collect::<Vec<_>>(),
+    )));
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+/// Writes data points to Parquet in record batches as they arrive, returning the number of rows.
+pub fn write_parquet(
+    dimensions: &[&str],
+    time_id: &str,
+    points: impl Iterator<Item = Result<DataPoint, failure::Error>>,
+    path: &Path,
+) -> Result<usize, failure::Error> {
+    write_batches(dimensions, time_id, points, path, BATCH_ROWS)
+}
+
+fn write_batches(
+    dimensions: &[&str],
+    time_id: &str,
+    mut points: impl Iterator<Item = Result<DataPoint, failure::Error>>,
+    path: &Path,
+    rows: usize,
+) -> Result<usize, failure::Error> {
+    let schema = to_record_batch(dimensions, time_id, &[])?.schema();
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(rows)
+        .build();
+    let file = std::fs::File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, schema, Some(properties))?;
+
+    let mut written = 0;
+    loop {
+        let chunk = points.by_ref().take(rows).collect::<Result<Vec<_>, _>>()?;
+        if chunk.is_empty() {
+            break;
+        }
+        writer.write(&to_record_batch(dimensions, time_id, &chunk)?)?;
+        written += chunk.len();
+    }
+    writer.close()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::models::data::DatasetContainer;
+    use super::super::DataPoint;
+    use arrow::array::{Array, Date32Array, Float64Array, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_record_batch_and_parquet() {
+        let container: DatasetContainer = serde_json::from_str(include_str!(
+            "../../test/data/dst.data.response.bil51.large.json"
+        ))
+        .unwrap();
+        let dimensions = &container.dataset.dimension;
+        // A suppressed cell is written as a null, not a zero.
+        let mut values = container.dataset.value.clone();
+        values[1] = None;
+        let points = DataPoint::from_dimensions_and_data(dimensions, &values).unwrap();
+
+        let ids: Vec<&str> = dimensions
+            .id
+            .iter()
+            .filter(|id| !dimensions.role.metric.contains(id))
+            .map(|id| id.as_str())
+            .collect();
+        let batch = super::to_record_batch(&ids, "Tid", &points).unwrap();
+
+        assert_eq!(batch.num_rows(), values.len());
+        let names: Vec<String> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect();
+        assert_eq!(names.last().unwrap(), "value");
+        assert_eq!(names[names.len() - 2], "Tid");
+
+        let tid = batch.column(names.len() - 2);
+        let tid = tid.as_any().downcast_ref::<Date32Array>().unwrap();
+        assert!(tid.value_as_date(0).is_some());
+        let value = batch.column(names.len() - 1);
+        let value = value.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(value.iter().collect::<Vec<_>>(), values);
+        assert!(batch.schema().field(names.len() - 1).is_nullable());
+        assert_eq!(names, vec!["EJER", "DRIV", "Tid", "value"]);
+        let first = batch.column(0);
+        assert!(first.as_any().downcast_ref::<StringArray>().is_some());
+
+        // Written in batches of 10 rows, each its own row group.
+        let dir = std::env::temp_dir().join(format!("klima.columnar.{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bil51.parquet");
+        let written =
+            super::write_batches(&ids, "Tid", points.clone().into_iter().map(Ok), &path, 10)
+                .unwrap();
+        assert_eq!(written, values.len());
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(
+            builder.metadata().num_row_groups(),
+            values.len().div_ceil(10)
+        );
+        let rows: Vec<DataPoint> = builder
+            .build()
+            .unwrap()
+            .flat_map(|batch| {
+                let batch = batch.unwrap();
+                let value = batch.column(names.len() - 1);
+                let value = value.as_any().downcast_ref::<Float64Array>().unwrap();
+                let driv = batch.column(1);
+                let driv = driv.as_any().downcast_ref::<StringArray>().unwrap();
+                (0..batch.num_rows())
+                    .map(|i| DataPoint {
+                        value: value.is_valid(i).then(|| value.value(i)),
+                        tags: im::ordmap! {"DRIV".to_string() => driv.value(i).to_string()},
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(rows.len(), values.len());
+        assert!(
+            rows.iter()
+                .zip(&points)
+                .all(|(row, point)| row.value == point.value
+                    && row.tags["DRIV"] == point.tags["DRIV"])
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_batch_errors() {
+        let point = |tags: &[(&str, &str)]| DataPoint {
+            value: Some(1.0),
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+        let batch = |point| super::to_record_batch(&["DRIV", "Tid"], "Tid", &[point]);
+
+        assert!(batch(point(&[("DRIV", "El"), ("Tid", "2020M01")])).is_ok());
+        assert!(batch(point(&[("Tid", "2020M01")])).is_err());
+        assert!(batch(point(&[("DRIV", "El"), ("Tid", "2020U01")])).is_err());
+    }
+}
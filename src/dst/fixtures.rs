@@ -0,0 +1,139 @@
+//! Recording of StatBank and Eurostat requests and responses to fixture files, and replaying
+//! them without network.
+//!
+//! Each exchange is one pretty-printed file named after the endpoint, the table and a hash of
+//! the request, e.g. `data.BIL51.3f2a9c01b7de.json`, so recordings are easy to review and diff.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub enum Fixtures {
+    /// Sends requests as usual and writes every successful exchange to the directory.
+    Record(PathBuf),
+    /// Answers requests from the directory only, failing for requests that were never recorded.
+    Replay(PathBuf),
+}
+
+#[derive(Deserialize, Serialize)]
+struct Exchange {
+    path: String,
+    request: serde_json::Value,
+    response: serde_json::Value,
+}
+
+/// The path of the URL, so recordings replay against any API base.
+fn url_path(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .map(|u| u.path().to_string())
+        .unwrap_or_else(|_| url.to_string())
+}
+
+fn file(dir: &Path, url: &str, request: &[u8]) -> Result<PathBuf, failure::Error> {
+    let path = url_path(url);
+    let endpoint = path.rsplit('/').next().unwrap_or_default();
+    let table = serde_json::from_slice::<serde_json::Value>(request)?
+        .get("table")
+        .and_then(|t| t.as_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let mut hasher = Sha256::new();
+    hasher.update(path.as_bytes());
+    hasher.update([0]);
+    hasher.update(request);
+    let hash: String = hasher.finalize()[..6]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(dir.join(format!("{}.{}.{}.json", endpoint, table, hash)))
+}
+
+fn load(dir: &Path, url: &str, request: &[u8]) -> Result<Vec<u8>, failure::Error> {
+    let file = file(dir, url, request)?;
+    let contents = std::fs::read(&file).map_err(|e| {
+        failure::format_err!(
+            "no recorded response for {} in {}: {}",
+            url,
+            file.display(),
+            e
+        )
+    })?;
+    let exchange: Exchange = serde_json::from_slice(&contents)?;
+    Ok(serde_json::to_vec(&exchange.response)?)
+}
+
+impl Fixtures {
+    /// The recorded response when replaying, or `None` when requests should be sent.
+    pub fn replay(&self, url: &str, request: &[u8]) -> Option<Result<Vec<u8>, failure::Error>> {
+        let dir = match self {
+            Fixtures::Record(_) => return None,
+            Fixtures::Replay(dir) => dir,
+        };
+        Some(load(dir, url, request))
+    }
+
+    /// Writes an exchange when recording.
+    pub fn record(&self, url: &str, request: &[u8], response: &[u8]) -> Result<(), failure::Error> {
+        let dir = match self {
+            Fixtures::Record(dir) => dir,
+            Fixtures::Replay(_) => return Ok(()),
+        };
+        std::fs::create_dir_all(dir)?;
+        let exchange = Exchange {
+            path: url_path(url),
+            request: serde_json::from_slice(request)?,
+            response: serde_json::from_slice(response)?,
+        };
+        let mut contents = serde_json::to_vec_pretty(&exchange)?;
+        contents.push(b'\n');
+        std::fs::write(file(dir, url, request)?, contents)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fixtures;
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("klima.fixtures.{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let request = br#"{"table":"BIL51","format":"JSONSTAT","variables":[]}"#;
+        let response = br#"{"dataset": {"value": [1, null]}}"#;
+
+        let record = Fixtures::Record(dir.clone());
+        assert!(record
+            .replay("https://api.statbank.dk/v1/data", request)
+            .is_none());
+        record
+            .record("https://api.statbank.dk/v1/data", request, response)
+            .unwrap();
+
+        let files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].starts_with("data.BIL51."));
+
+        // Any API base replays the same recording.
+        let replay = Fixtures::Replay(dir.clone());
+        let replayed = replay
+            .replay("http://127.0.0.1:8080/v1/data", request)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&replayed).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(response).unwrap()
+        );
+        assert!(replay
+            .replay("http://127.0.0.1:8080/v1/tableinfo", request)
+            .unwrap()
+            .is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
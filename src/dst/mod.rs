@@ -1,15 +1,27 @@
-mod models;
+pub mod bulk;
+pub mod cache;
+pub mod columnar;
+pub mod fixtures;
+pub mod models;
+pub mod nonblocking;
+pub mod retry;
+pub mod split;
+#[cfg(test)]
+pub mod standin;
 
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::future::Future;
 
-use crate::table::{TimeSeries, TimeSeriesGroup};
-use models::data::{DataRequest, DatasetContainer, Dimension, Dimensions, VariableRequest};
-use models::metadata::{Metadata, MetadataRequest};
+use crate::table::{Footnote, Provenance, TimeSeries, TimeSeriesGroup, Unit};
+use fixtures::Fixtures;
+use models::data::{DataRequest, DatasetContainer, Dimensions, VariableRequest};
+use models::metadata::{Metadata, MetadataRequest, Variable};
 use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DataPoint {
-    pub value: i64,
+    /// `None` for missing and suppressed cells, e.g. StatBank's `..`.
+    pub value: Option<f64>,
     pub tags: im::OrdMap<String, String>,
 }
 
@@ -17,7 +29,7 @@ impl DataPoint {
     fn join_dimensions_and_data(
         dimensions: &[(&str, Vec<&str>)],
         tags: im::OrdMap<String, String>,
-        data: &[i64],
+        data: &[Option<f64>],
     ) -> im::Vector<DataPoint> {
         match dimensions.split_first() {
             None => im::Vector::unit(DataPoint {
@@ -37,154 +49,633 @@ impl DataPoint {
         }
     }
 
-    fn labels_sorted_by_index(dimension: &Dimension) -> Vec<&str> {
-        let swap = |(a, b)| (b, a);
-        let label = |id: &&String| dimension.category.label[id.as_str()].as_str();
-        dimension
-            .category
-            .index
-            .iter()
-            .map(swap)
-            .collect::<im::OrdMap<&u64, &String>>()
-            .values()
-            .map(label)
-            .collect()
+    /// The labels of a category in index order, checking that the index numbers them 0, 1, ...
+    fn labels_sorted_by_index<'a>(
+        id: &str,
+        index: &'a BTreeMap<String, u64>,
+        label: &'a BTreeMap<String, String>,
+    ) -> Result<Vec<&'a str>, failure::Error> {
+        let mut labels = vec![None; index.len()];
+        for (code, n) in index {
+            let slot = labels
+                .get_mut(*n as usize)
+                .ok_or_else(|| failure::format_err!("{} has index {} for {}", id, n, code))?;
+            if slot.is_some() {
+                failure::bail!("{} has index {} more than once", id, n);
+            }
+            let text = label
+                .get(code)
+                .ok_or_else(|| failure::format_err!("{} has no label for {}", id, code))?;
+            *slot = Some(text.as_str());
+        }
+        Ok(labels.into_iter().flatten().collect())
     }
 
-    fn from_dimensions_and_data(dimensions: &Dimensions, data: &[i64]) -> Vec<DataPoint> {
-        let d: Vec<(&str, Vec<&str>)> = dimensions
-            .id
-            .iter()
-            .filter(|id| !dimensions.role.metric.contains(id))
-            .map(|id| {
-                (
-                    id.as_str(),
-                    Self::labels_sorted_by_index(&dimensions.dimension[id]),
-                )
-            })
-            .collect();
+    /// Joins JSON-stat dimensions with their values, which must have one value per combination.
+    ///
+    /// Metric dimensions become tags only when they have more than one content code, so tables
+    /// with a single measure keep their series labels.
+    pub fn from_dimensions_and_data(
+        dimensions: &Dimensions,
+        data: &[Option<f64>],
+    ) -> Result<Vec<DataPoint>, failure::Error> {
+        if dimensions.id.len() != dimensions.size.len() {
+            failure::bail!(
+                "{} dimensions but {} sizes",
+                dimensions.id.len(),
+                dimensions.size.len()
+            );
+        }
+
+        let mut d: Vec<(&str, Vec<&str>)> = vec![];
+        for (id, size) in dimensions.id.iter().zip(&dimensions.size) {
+            let metric = dimensions.role.metric.contains(id);
+            let labels = if metric {
+                let category = &dimensions.contents_code.category;
+                Self::labels_sorted_by_index(id, &category.index, &category.label)?
+            } else {
+                let dimension = dimensions
+                    .dimension
+                    .get(id)
+                    .ok_or_else(|| failure::format_err!("no dimension {}", id))?;
+                Self::labels_sorted_by_index(
+                    id,
+                    &dimension.category.index,
+                    &dimension.category.label,
+                )?
+            };
+            if labels.len() as u64 != *size {
+                failure::bail!("{} has size {} but {} categories", id, size, labels.len());
+            }
+            if !(metric && labels.len() == 1) {
+                d.push((id.as_str(), labels));
+            }
+        }
+
+        let cells: u64 = dimensions.size.iter().product();
+        if cells != data.len() as u64 {
+            failure::bail!(
+                "dimensions of sizes {:?} make {} cells, but there are {} values",
+                dimensions.size,
+                cells,
+                data.len()
+            );
+        }
 
-        DataPoint::join_dimensions_and_data(&d, im::OrdMap::new(), data)
-            .into_iter()
-            .collect()
+        Ok(
+            DataPoint::join_dimensions_and_data(&d, im::OrdMap::new(), data)
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    /// Parses years, months and quarters as used by StatBank (2020M01, 2020K1) and Eurostat (2020-01, 2020-Q1).
+    pub fn parse_time(s: &str) -> Result<NaiveDate, failure::Error> {
+        Self::try_parse_time(s)
+            .ok_or_else(|| failure::format_err!("unsupported time period: {}", s))
     }
 
-    fn parse_time(s: &str) -> NaiveDate {
-        if s.len() == 7 && s.contains('M') {
-            NaiveDate::parse_from_str(&format!("{}D01", s), "%YM%mD%d")
-        } else {
-            NaiveDate::parse_from_str(&format!("{}M01D01", s), "%YM%mD%d")
+    /// Like `parse_time`, but returns `None` on unknown formats such as weeks or half years.
+    pub fn try_parse_time(s: &str) -> Option<NaiveDate> {
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Some(date);
         }
-        .expect("failed to understand date format")
+        let (year, period) = s.split_at_checked(4.min(s.len()))?;
+        let period = period.trim_start_matches('-');
+        let month = match period.chars().next() {
+            None => Some(1),
+            Some('M') => period[1..].parse().ok(),
+            Some('Q') | Some('K') => period[1..]
+                .parse::<u32>()
+                .ok()
+                .and_then(|q| q.checked_sub(1))
+                .map(|q| q * 3 + 1),
+            Some(_) => period.parse().ok(),
+        };
+        year.parse()
+            .ok()
+            .zip(month)
+            .and_then(|(year, month)| NaiveDate::from_ymd_opt(year, month, 1))
     }
 
-    fn to_timeseries(time_id: &str, data: Vec<DataPoint>) -> Vec<TimeSeries> {
+    /// Folds points into a series per tag set, leaving missing values out rather than as zeros.
+    pub fn to_timeseries(
+        time_id: &str,
+        data: impl IntoIterator<Item = DataPoint>,
+    ) -> Result<Vec<TimeSeries>, failure::Error> {
         let tmp: im::OrdMap<im::OrdSet<String>, TimeSeries> =
-            data.into_iter().fold(im::OrdMap::new(), |m, p| {
-                let time = Self::parse_time(&p.tags[time_id]);
+            data.into_iter().try_fold(im::OrdMap::new(), |m, p| {
+                let value = match p.value {
+                    Some(value) => value,
+                    None => return Ok(m),
+                };
+                let time = p
+                    .tags
+                    .get(time_id)
+                    .ok_or_else(|| failure::format_err!("data point without {}", time_id))?;
+                let time = Self::parse_time(time)?;
                 let tags: im::OrdSet<String> = p.tags.without(time_id).values().collect();
-                let new = TimeSeries::unit(tags.clone(), time, p.value);
-                m.update_with(tags, new, std::ops::Add::add)
-            });
-        tmp.into_iter().map(|(_, ts)| ts).collect()
+                let new = TimeSeries::unit(tags.clone(), time, value);
+                Ok::<_, failure::Error>(m.update_with(tags, new, std::ops::Add::add))
+            })?;
+        Ok(tmp.into_iter().map(|(_, ts)| ts).collect())
+    }
+}
+
+const API: &str = "https://api.statbank.dk/v1";
+
+/// The response format used for data requests.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    JsonStat,
+    /// Semicolon separated and streamed by StatBank as it is produced; meant for very large extracts.
+    Bulk,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::JsonStat => "JSONSTAT",
+            Format::Bulk => "BULK",
+        }
+    }
+}
+
+/// The points of a selection, with what else the responses told about them.
+struct Fetched {
+    points: Box<dyn Iterator<Item = Result<DataPoint, failure::Error>>>,
+    /// The tags of every point, in table order.
+    dimensions: Vec<String>,
+    /// When the points were fetched, if any came from the cache.
+    stale: Option<DateTime<Utc>>,
+    /// The unit of each content code by its label; unknown for streaming formats.
+    units: Vec<(String, Unit)>,
+}
+
+impl Fetched {
+    /// Folds the points into series as they arrive, stopping at the first error, and cites them.
+    fn into_group(
+        self,
+        metadata: &Metadata,
+        field_selector: &BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        let mut error = Ok(());
+        let points = self.points.scan(&mut error, |error, point| {
+            point.map_err(|e| **error = Err(e)).ok()
+        });
+        let series = DataPoint::to_timeseries(time_id(metadata)?, points);
+        error?;
+        let series = series?;
+        Ok(TimeSeriesGroup::new(metadata.updated, series)
+            .stale(self.stale)
+            .with_units(self.units)
+            .with_provenance(provenance(metadata, field_selector, self.stale)))
+    }
+}
+
+fn time_id(metadata: &Metadata) -> Result<&str, failure::Error> {
+    metadata
+        .variables
+        .iter()
+        .find(|v| v.time)
+        .map(|v| v.id.as_str())
+        .ok_or_else(|| failure::format_err!("{} has no time variable", metadata.id))
+}
+
+/// Fetches a selection as JSONSTAT, in parts when it is too large: the one fetch path of both
+/// the blocking and the async client, which only differ in how they `post` a data request.
+async fn fetch_jsonstat<F, R>(
+    table: &str,
+    metadata: &Metadata,
+    eliminate: bool,
+    field_selector: &BTreeMap<String, Vec<String>>,
+    stale: Option<DateTime<Utc>>,
+    post: F,
+) -> Result<Fetched, failure::Error>
+where
+    F: Fn(Vec<u8>) -> R,
+    R: Future<Output = Result<(Vec<u8>, Option<DateTime<Utc>>), failure::Error>>,
+{
+    let mut points = vec![];
+    let mut stale = stale;
+    let mut units = BTreeMap::new();
+    let dimensions = selection_tags(metadata, eliminate, field_selector);
+    for part in selections(metadata, eliminate, Format::JsonStat, field_selector)? {
+        let request = serde_json::to_vec(&data_request(table, Format::JsonStat, &part))?;
+        let (response, fetched) = post(request).await?;
+        let response: DatasetContainer = serde_json::from_slice(&response)?;
+        add_part(&mut points, &mut units, &dimensions, response)?;
+        stale = stale.into_iter().chain(fetched).min();
     }
+    Ok(Fetched {
+        points: Box::new(points.into_iter().map(Ok)),
+        dimensions,
+        stale,
+        units: units.into_iter().collect(),
+    })
 }
 
 pub struct Table {
     client: reqwest::blocking::Client,
+    api: String,
+    policy: retry::Policy,
+    cache: Option<cache::Cache>,
+    fixtures: Option<Fixtures>,
     table: String,
     metadata: Metadata,
+    /// When the metadata was fetched, if it came from the cache because StatBank failed.
+    stale: Option<DateTime<Utc>>,
+    format: Format,
+    eliminate: bool,
 }
 
 impl Table {
     pub fn new(table: &str) -> Result<Table, failure::Error> {
-        let client = reqwest::blocking::Client::new();
-        let metadata = client
-            .post("https://api.statbank.dk/v1/tableinfo")
-            .json(&MetadataRequest { table })
-            .send()?
-            .json()?;
+        Table::connect(API, table, retry::Policy::default(), None, None)
+    }
+
+    /// Opens a table in a StatBank compatible API, retrying failed requests according to `policy`
+    /// and falling back to responses in `cache` when they still fail. With `fixtures`, JSON
+    /// exchanges are recorded to or replayed from a directory like `nonblocking::Client` does.
+    pub fn connect(
+        api: &str,
+        table: &str,
+        policy: retry::Policy,
+        cache: Option<cache::Cache>,
+        fixtures: Option<Fixtures>,
+    ) -> Result<Table, failure::Error> {
+        // Buffered requests set a timeout of their own for the whole exchange; the client timeout
+        // bounds the time to the headers and every read of a streamed body separately.
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(policy.connect_timeout)
+            .timeout(policy.timeout)
+            .build()?;
+        let api = api.trim_end_matches('/').to_string();
+        let (metadata, stale) = post(
+            &client,
+            &policy,
+            cache.as_ref(),
+            fixtures.as_ref(),
+            &format!("{}/tableinfo", api),
+            serde_json::to_vec(&MetadataRequest { table })?,
+        )?;
 
         Ok(Table {
             client,
-            metadata,
+            api,
+            policy,
+            cache,
+            fixtures,
+            metadata: serde_json::from_slice(&metadata)?,
+            stale,
             table: table.into(),
+            format: Format::JsonStat,
+            eliminate: false,
         })
     }
 
+    /// Leaves out unselected variables that StatBank can eliminate, so they are aggregated
+    /// by StatBank rather than fetched value by value and summed, which double counts totals.
+    #[allow(
+        dead_code,
+        reason = "the dashboard eliminates through `nonblocking::Table`"
+    )]
+    pub fn eliminate(self) -> Table {
+        Table {
+            eliminate: true,
+            ..self
+        }
+    }
+
+    pub fn format(self, format: Format) -> Table {
+        Table { format, ..self }
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
     pub fn fetch(
         &self,
         field_selector: BTreeMap<String, Vec<String>>,
     ) -> Result<TimeSeriesGroup, failure::Error> {
-        let id_selector: BTreeMap<String, Vec<&str>> = field_selector
-            .into_iter()
-            .map(|(k, v)| {
-                let metadata = self.metadata.variables.iter().find(|v| v.id == k).unwrap();
-                let ids: Vec<&str> = v
-                    .into_iter()
-                    .map(|text| {
-                        metadata
-                            .values
-                            .iter()
-                            .find(|v| v.text == text)
-                            .unwrap_or_else(|| panic!("no such variable value: {}", text))
-                            .id
-                            .as_str()
-                    })
-                    .collect();
-                (k, ids)
-            })
-            .collect();
+        self.fetch_points(&field_selector)?
+            .into_group(&self.metadata, &field_selector)
+    }
 
-        let request = DataRequest {
-            table: self.table.as_str(),
-            format: "JSONSTAT".to_string(),
-            variables: self
-                .metadata
-                .variables
-                .iter()
-                .map(|v| VariableRequest {
-                    code: v.id.as_str(),
-                    values: id_selector
-                        .get(&v.id)
-                        .cloned()
-                        .unwrap_or(vec!["*"])
-                        .to_owned(),
-                })
-                .collect(),
+    /// Writes a selection to Parquet with one column per variable plus the value, returning the
+    /// number of rows. Streaming formats are written batch by batch as they arrive.
+    pub fn write_parquet(
+        &self,
+        field_selector: BTreeMap<String, Vec<String>>,
+        path: &std::path::Path,
+    ) -> Result<usize, failure::Error> {
+        let fetched = self.fetch_points(&field_selector)?;
+        let dimensions: Vec<&str> = fetched.dimensions.iter().map(String::as_str).collect();
+        columnar::write_parquet(&dimensions, time_id(&self.metadata)?, fetched.points, path)
+    }
+
+    /// The points of a selection.
+    ///
+    /// Streaming formats are read straight off the response, so they are never cached, recorded
+    /// or replayed.
+    fn fetch_points(
+        &self,
+        field_selector: &BTreeMap<String, Vec<String>>,
+    ) -> Result<Fetched, failure::Error> {
+        let url = format!("{}/data", self.api);
+        if self.format != Format::JsonStat {
+            if let Some(Fixtures::Replay(_)) = self.fixtures {
+                failure::bail!(
+                    "{:?} responses are streamed and cannot be replayed",
+                    self.format
+                );
+            }
+            let parts = selections(&self.metadata, self.eliminate, self.format, field_selector)?;
+            let request = data_request(&self.table, self.format, &parts[0]);
+            let response = retry::blocking(&self.policy, &url, || {
+                self.client
+                    .post(&url)
+                    .json(&request)
+                    .send()?
+                    .error_for_status()
+            })?;
+            let reader = bulk::Reader::new(response, &self.metadata.variables)?;
+            return Ok(Fetched {
+                dimensions: reader.columns().to_vec(),
+                points: Box::new(reader),
+                stale: self.stale,
+                units: vec![],
+            });
+        }
+
+        // Each post completes before its future is polled, so no async runtime is needed.
+        let post = |request| {
+            let response = post(
+                &self.client,
+                &self.policy,
+                self.cache.as_ref(),
+                self.fixtures.as_ref(),
+                &url,
+                request,
+            );
+            async { response }
         };
+        futures::executor::block_on(fetch_jsonstat(
+            &self.table,
+            &self.metadata,
+            self.eliminate,
+            field_selector,
+            self.stale,
+            post,
+        ))
+    }
+}
 
-        let response: DatasetContainer = self
-            .client
-            .post("https://api.statbank.dk/v1/data")
-            .json(&request)
+/// Posts a JSON request with retries, falling back to the cache when it fails.
+fn post(
+    client: &reqwest::blocking::Client,
+    policy: &retry::Policy,
+    cache: Option<&cache::Cache>,
+    fixtures: Option<&Fixtures>,
+    url: &str,
+    request: Vec<u8>,
+) -> Result<(Vec<u8>, Option<DateTime<Utc>>), failure::Error> {
+    if let Some(replayed) = fixtures.and_then(|f| f.replay(url, &request)) {
+        return Ok((replayed?, None));
+    }
+
+    let result = retry::blocking(policy, url, || {
+        client
+            .post(url)
+            .timeout(policy.timeout)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(request.clone())
             .send()?
-            .json()?;
+            .error_for_status()?
+            .bytes()
+    })
+    .map(|b| b.to_vec());
+    if let (Some(fixtures), Ok(response)) = (fixtures, &result) {
+        fixtures.record(url, &request, response)?;
+    }
+    cache::or_stale(cache, url, &request, result)
+}
 
-        let values: Vec<_> = response
-            .dataset
-            .value
-            .iter()
-            .map(|v| v.unwrap_or(0))
-            .collect();
+/// The variables to send in a data request for the selection.
+fn requested<'a>(
+    metadata: &'a Metadata,
+    eliminate: bool,
+    field_selector: &BTreeMap<String, Vec<String>>,
+) -> Vec<&'a Variable> {
+    metadata
+        .variables
+        .iter()
+        .filter(|v| !eliminate || !v.elimination || field_selector.contains_key(&v.id))
+        .collect()
+}
 
-        let time_id = self.metadata.variables.iter().find(|v| v.time).unwrap();
+/// The value ids to request per variable, split in several parts if needed to stay under the cell limit.
+fn selections<'a>(
+    metadata: &'a Metadata,
+    eliminate: bool,
+    format: Format,
+    field_selector: &BTreeMap<String, Vec<String>>,
+) -> Result<Vec<split::Selection<'a>>, failure::Error> {
+    let id_selector = field_selector
+        .iter()
+        .map(|(k, v)| {
+            let metadata = metadata
+                .variables
+                .iter()
+                .find(|v| &v.id == k)
+                .ok_or_else(|| failure::format_err!("no such variable: {}", k))?;
+            let ids = v
+                .iter()
+                .map(|text| {
+                    metadata
+                        .values
+                        .iter()
+                        .find(|v| &v.text == text)
+                        .map(|v| v.id.as_str())
+                        .ok_or_else(|| failure::format_err!("no such variable value: {}", text))
+                })
+                .collect::<Result<Vec<&str>, failure::Error>>()?;
+            Ok((metadata.id.as_str(), ids))
+        })
+        .collect::<Result<BTreeMap<&str, Vec<&str>>, failure::Error>>()?;
 
-        Ok(TimeSeriesGroup::new(
-            self.metadata.updated,
-            DataPoint::to_timeseries(
-                &time_id.id,
-                DataPoint::from_dimensions_and_data(&response.dataset.dimension, &values),
-            ),
+    let variables = requested(metadata, eliminate, field_selector);
+    let wildcard: split::Selection = variables
+        .iter()
+        .map(|v| {
+            let values = id_selector.get(v.id.as_str()).cloned().unwrap_or(vec!["*"]);
+            (v.id.as_str(), values)
+        })
+        .collect();
+    if format != Format::JsonStat {
+        return Ok(vec![wildcard]);
+    }
+
+    // Only streaming formats may exceed the cell limit, so larger selections are fetched in parts.
+    let explicit: split::Selection = variables
+        .iter()
+        .map(|v| {
+            let values = id_selector
+                .get(v.id.as_str())
+                .cloned()
+                .unwrap_or_else(|| v.values.iter().map(|v| v.id.as_str()).collect());
+            (v.id.as_str(), values)
+        })
+        .collect();
+    if split::cells(&explicit) <= split::CELL_LIMIT {
+        Ok(vec![wildcard])
+    } else {
+        Ok(split::split(
+            explicit,
+            time_id(metadata)?,
+            split::CELL_LIMIT,
         ))
     }
 }
 
+fn data_request<'a>(
+    table: &'a str,
+    format: Format,
+    selection: &'a [(&'a str, Vec<&'a str>)],
+) -> DataRequest<'a> {
+    DataRequest {
+        table,
+        format: format.name().to_string(),
+        // Texts rather than codes, as in the JSONSTAT labels.
+        value_presentation: match format {
+            Format::JsonStat => None,
+            Format::Bulk => Some("Value"),
+        },
+        variables: selection
+            .iter()
+            .map(|(code, values)| VariableRequest {
+                code,
+                values: values.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// Cites a table, naming the selected variables by their texts.
+///
+/// Data from the cache was fetched when it was stored, otherwise it is fetched now.
+pub fn provenance(
+    metadata: &Metadata,
+    field_selector: &BTreeMap<String, Vec<String>>,
+    stale: Option<DateTime<Utc>>,
+) -> Provenance {
+    Provenance {
+        source: "Danmarks Statistik".to_string(),
+        table: metadata.id.clone(),
+        title: metadata.text.clone(),
+        unit: metadata.unit.clone(),
+        documentation: Some(metadata.documentation.url.clone()),
+        selection: selection(&metadata.variables, field_selector),
+        fetched: stale.unwrap_or_else(Utc::now),
+        footnotes: metadata
+            .footnote
+            .iter()
+            .map(|f| Footnote {
+                text: f.text.clone(),
+                mandatory: f.mandatory,
+            })
+            .collect(),
+    }
+}
+
+/// A selection keyed by the texts of the variables rather than their ids, for citing.
+pub fn selection(
+    variables: &[Variable],
+    field_selector: &BTreeMap<String, Vec<String>>,
+) -> BTreeMap<String, Vec<String>> {
+    field_selector
+        .iter()
+        .map(|(id, texts)| {
+            let variable = variables.iter().find(|v| &v.id == id);
+            (variable.map_or(id, |v| &v.text).clone(), texts.clone())
+        })
+        .collect()
+}
+
+/// The unit of each content code in a response, by its label.
+pub fn jsonstat_units(dimensions: &Dimensions) -> Vec<(String, Unit)> {
+    let category = &dimensions.contents_code.category;
+    category
+        .unit
+        .iter()
+        .filter_map(|(code, unit)| {
+            let unit = Unit {
+                base: unit.base.clone(),
+                decimals: unit.decimals,
+            };
+            Some((category.label.get(code)?.clone(), unit))
+        })
+        .collect()
+}
+
+/// The tags of the points of a whole selection, in table order. Content codes are a tag only
+/// when more than one is selected, as in `DataPoint::from_dimensions_and_data`.
+fn selection_tags(
+    metadata: &Metadata,
+    eliminate: bool,
+    field_selector: &BTreeMap<String, Vec<String>>,
+) -> Vec<String> {
+    requested(metadata, eliminate, field_selector)
+        .into_iter()
+        .filter(|v| {
+            let selected = field_selector.get(&v.id).map_or(v.values.len(), Vec::len);
+            v.id != bulk::CONTENTS_CODE || selected > 1
+        })
+        .map(|v| v.id.clone())
+        .collect()
+}
+
+/// Adds the points and units of one part of a selection with the tags in `dimensions`, so a
+/// part with a single one of several selected content codes still tags its points with it.
+fn add_part(
+    points: &mut Vec<DataPoint>,
+    units: &mut BTreeMap<String, Unit>,
+    dimensions: &[String],
+    response: DatasetContainer,
+) -> Result<(), failure::Error> {
+    let dimension = &response.dataset.dimension;
+    units.extend(jsonstat_units(dimension));
+    let part = DataPoint::from_dimensions_and_data(dimension, &response.dataset.value)?;
+
+    // Only a single content code is left out of the tags of the part's points.
+    let labels = &dimension.contents_code.category.label;
+    let dropped: Vec<(&String, &String)> = match labels.values().next() {
+        Some(label) if labels.len() == 1 => dimension
+            .role
+            .metric
+            .iter()
+            .filter(|id| dimensions.contains(id))
+            .map(|id| (id, label))
+            .collect(),
+        _ => vec![],
+    };
+    points.extend(part.into_iter().map(|mut point| {
+        for (id, label) in &dropped {
+            point.tags.insert(id.to_string(), label.to_string());
+        }
+        point
+    }));
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::DataPoint;
     use super::DatasetContainer;
+    use super::Dimensions;
     use super::Metadata;
+    use proptest::prelude::*;
 
     #[test]
     fn test_from_dimensions_and_data() {
@@ -202,7 +693,7 @@ mod tests {
             for ejer in &dimensions[1].1 {
                 for data in &dimensions[2].1 {
                     for tid in &dimensions[3].1 {
-                        let n = values.len() as i64;
+                        let n = Some(values.len() as f64);
                         values.push(n);
                         expect.push(DataPoint {
                             tags: vec![
@@ -224,6 +715,258 @@ mod tests {
         assert_eq!(got, expect.into());
     }
 
+    #[test]
+    fn test_eliminate() {
+        let table = super::Table {
+            client: reqwest::blocking::Client::new(),
+            api: super::API.to_string(),
+            policy: Default::default(),
+            cache: None,
+            fixtures: None,
+            stale: None,
+            table: "BIL51".to_string(),
+            metadata: serde_json::from_str(include_str!(
+                "../../test/data/dst.metadata.response.bil51.json"
+            ))
+            .unwrap(),
+            format: super::Format::JsonStat,
+            eliminate: false,
+        };
+        let ids = |table: &super::Table, selector| -> Vec<String> {
+            super::requested(&table.metadata, table.eliminate, &selector)
+                .into_iter()
+                .map(|v| v.id.clone())
+                .collect()
+        };
+
+        let mut selector = std::collections::BTreeMap::new();
+        selector.insert("DRIV".to_string(), vec!["Benzin".to_string()]);
+        assert_eq!(ids(&table, selector.clone()), vec!["EJER", "DRIV", "Tid"]);
+
+        let table = table.eliminate();
+        assert_eq!(ids(&table, selector.clone()), vec!["DRIV", "Tid"]);
+
+        selector.insert("EJER".to_string(), vec!["Erhvervene".to_string()]);
+        assert_eq!(ids(&table, selector), vec!["EJER", "DRIV", "Tid"]);
+    }
+
+    #[test]
+    fn test_unknown_selection() {
+        let metadata: super::Metadata = serde_json::from_str(include_str!(
+            "../../test/data/dst.metadata.response.bil51.json"
+        ))
+        .unwrap();
+        let selections = |id: &str, text: &str| {
+            let mut selector = std::collections::BTreeMap::new();
+            selector.insert(id.to_string(), vec![text.to_string()]);
+            super::selections(&metadata, false, super::Format::JsonStat, &selector)
+                .map(|parts| parts.len())
+                .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(selections("DRIV", "Benzin"), Ok(1));
+        assert_eq!(
+            selections("DRIV", "Kul"),
+            Err("no such variable value: Kul".to_string())
+        );
+        assert_eq!(
+            selections("FARVE", "Rød"),
+            Err("no such variable: FARVE".to_string())
+        );
+    }
+
+    #[test]
+    fn test_retry() {
+        use super::retry::Policy;
+        use super::standin::{Failure, StandIn};
+        use std::time::Duration;
+
+        let policy = Policy {
+            attempts: 3,
+            backoff: Duration::from_millis(10),
+            timeout: Duration::from_millis(500),
+            interval: Duration::ZERO,
+            ..Policy::default()
+        };
+
+        // A 503 and a timeout are retried, but an unreadable response is not.
+        let standin = StandIn::start(
+            Duration::ZERO,
+            vec![
+                Failure::Status(503),
+                Failure::Hang(Duration::from_secs(1)),
+                Failure::Status(200),
+                Failure::Status(500),
+            ],
+        );
+        let table = super::Table::connect(&standin.api, "BIL51", policy.clone(), None, None);
+        assert!(table.is_err());
+        assert_eq!(standin.requests(), 3);
+
+        // A 500 on data is retried as well.
+        let table =
+            super::Table::connect(&standin.api, "BIL51", policy.clone(), None, None).unwrap();
+        let group = table.fetch(Default::default()).unwrap();
+        assert_eq!(group.len(), 4);
+        assert_eq!(standin.requests(), 6);
+
+        // Client errors are not retried.
+        let standin = StandIn::start(Duration::ZERO, vec![Failure::Status(404)]);
+        assert!(super::Table::connect(&standin.api, "BIL51", policy, None, None).is_err());
+        assert_eq!(standin.requests(), 1);
+    }
+
+    #[test]
+    fn test_slow_stream() {
+        use super::retry::Policy;
+        use super::standin::StandIn;
+        use std::time::Duration;
+
+        // The timeout bounds buffered requests, but not a BULK stream that keeps arriving.
+        let policy = Policy {
+            attempts: 1,
+            timeout: Duration::from_millis(200),
+            interval: Duration::ZERO,
+            ..Policy::default()
+        };
+        let standin = StandIn::start(Duration::ZERO, vec![]);
+        standin.set_stream_pause(Duration::from_millis(100));
+        let table = super::Table::connect(&standin.api, "BIL51", policy, None, None)
+            .unwrap()
+            .format(super::Format::Bulk);
+        assert_eq!(table.fetch(Default::default()).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_stalled_stream() {
+        use super::retry::Policy;
+        use super::standin::{Failure, StandIn};
+        use std::time::{Duration, Instant};
+
+        let policy = Policy {
+            attempts: 1,
+            timeout: Duration::from_millis(200),
+            interval: Duration::ZERO,
+            ..Policy::default()
+        };
+
+        // A BULK request that never gets its headers times out.
+        let standin = StandIn::start(Duration::ZERO, vec![]);
+        let table = super::Table::connect(&standin.api, "BIL51", policy.clone(), None, None)
+            .unwrap()
+            .format(super::Format::Bulk);
+        let standin = StandIn::start(Duration::ZERO, vec![Failure::Hang(Duration::from_secs(5))]);
+        let table = super::Table {
+            api: standin.api.clone(),
+            ..table
+        };
+        let start = Instant::now();
+        assert!(table.fetch(Default::default()).is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        // So does a stream that stops arriving halfway.
+        let standin = StandIn::start(Duration::ZERO, vec![]);
+        standin.set_stream_pause(Duration::from_secs(5));
+        let table = super::Table::connect(&standin.api, "BIL51", policy, None, None)
+            .unwrap()
+            .format(super::Format::Bulk);
+        let start = Instant::now();
+        assert!(table.fetch(Default::default()).is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_stale_fallback() {
+        use super::cache::Cache;
+        use super::retry::Policy;
+        use super::standin::StandIn;
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(format!("klima.table.{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let policy = Policy {
+            attempts: 1,
+            interval: Duration::ZERO,
+            ..Policy::default()
+        };
+        let standin = StandIn::start(Duration::ZERO, vec![]);
+        let connect = || {
+            super::Table::connect(
+                &standin.api,
+                "BIL51",
+                policy.clone(),
+                Some(Cache::new(&dir)),
+                None,
+            )
+        };
+
+        let fresh = connect().unwrap().fetch(Default::default()).unwrap();
+        assert_eq!(fresh.stale_since(), None);
+
+        standin.set_down(true);
+        let stale = connect().unwrap().fetch(Default::default()).unwrap();
+        assert!(stale.stale_since().is_some());
+        assert_eq!(stale.len(), 4);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        use super::fixtures::Fixtures;
+        use super::retry::Policy;
+        use super::standin::StandIn;
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(format!("klima.record.{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let policy = Policy {
+            attempts: 1,
+            interval: Duration::ZERO,
+            ..Policy::default()
+        };
+        let standin = StandIn::start(Duration::ZERO, vec![]);
+        let connect = |fixtures| {
+            super::Table::connect(&standin.api, "BIL51", policy.clone(), None, Some(fixtures))
+        };
+
+        let recorded = connect(Fixtures::Record(dir.clone()))
+            .unwrap()
+            .fetch(Default::default())
+            .unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        standin.set_down(true);
+        let replay = connect(Fixtures::Replay(dir.clone())).unwrap();
+        let replayed = replay.fetch(Default::default()).unwrap();
+        assert_eq!(recorded.to_json(), replayed.to_json());
+        assert!(replay
+            .format(super::Format::Bulk)
+            .fetch(Default::default())
+            .is_err());
+        assert_eq!(standin.requests(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let date = |y, m| chrono::NaiveDate::from_ymd_opt(y, m, 1).unwrap();
+        assert_eq!(DataPoint::parse_time("2020").unwrap(), date(2020, 1));
+        assert_eq!(DataPoint::parse_time("2020M07").unwrap(), date(2020, 7));
+        assert_eq!(DataPoint::parse_time("2020K3").unwrap(), date(2020, 7));
+        assert_eq!(DataPoint::parse_time("2020-07").unwrap(), date(2020, 7));
+        assert_eq!(DataPoint::parse_time("2020-Q2").unwrap(), date(2020, 4));
+        assert_eq!(DataPoint::parse_time("2020-04-01").unwrap(), date(2020, 4));
+        assert_eq!(DataPoint::try_parse_time("2020W12"), None);
+        for code in &["2020U01", "2020H1"] {
+            assert_eq!(
+                DataPoint::parse_time(code).unwrap_err().to_string(),
+                format!("unsupported time period: {}", code)
+            );
+        }
+    }
+
     #[test]
     fn test() {
         assert!(serde_json::from_str::<Metadata>(include_str!(
@@ -236,4 +979,230 @@ mod tests {
         ))
         .is_ok());
     }
+
+    #[test]
+    fn test_jsonstat_validation() {
+        let original: serde_json::Value =
+            serde_json::from_str(include_str!("../../test/data/dst.data.response.bil51.json"))
+                .unwrap();
+        let points = |json: &serde_json::Value| {
+            let container: DatasetContainer = serde_json::from_value(json.clone()).unwrap();
+            DataPoint::from_dimensions_and_data(
+                &container.dataset.dimension,
+                &container.dataset.value,
+            )
+        };
+        let single = points(&original).unwrap();
+        assert!(single.iter().all(|p| !p.tags.contains_key("ContentsCode")));
+
+        let mut short = original.clone();
+        short["dataset"]["value"].as_array_mut().unwrap().pop();
+        let error = points(&short).unwrap_err().to_string();
+        assert!(error.contains("values"), "{}", error);
+
+        let mut size = original.clone();
+        size["dataset"]["dimension"]["size"][0] = 5.into();
+        assert!(points(&size).is_err());
+
+        let mut index = original.clone();
+        let category = &mut index["dataset"]["dimension"]["Tid"]["category"]["index"];
+        let first = category.as_object().unwrap().keys().next().unwrap().clone();
+        category[first] = 7.into();
+        assert!(points(&index).is_err());
+
+        // A second content code doubles the values, which are told apart by a ContentsCode tag.
+        let mut metrics = original.clone();
+        let dimension = &mut metrics["dataset"]["dimension"];
+        let position = dimension["id"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .position(|id| id == "ContentsCode")
+            .unwrap();
+        dimension["size"][position] = 2.into();
+        let contents = &mut dimension["ContentsCode"]["category"];
+        contents["index"]["BIL51P"] = 1.into();
+        contents["label"]["BIL51P"] = "Andel i procent".into();
+        contents["unit"]["BIL51P"] = serde_json::json!({"base": "pct.", "decimals": 1});
+        assert!(points(&metrics).is_err());
+
+        metrics["dataset"]["value"] = serde_json::json!([1200, 34]);
+        let both = points(&metrics).unwrap();
+        assert_eq!(both.len(), 2);
+        assert_eq!(both[1].value, Some(34.0));
+        assert_eq!(both[1].tags["ContentsCode"], "Andel i procent");
+        assert_ne!(both[0].tags["ContentsCode"], "Andel i procent");
+    }
+
+    #[test]
+    fn test_split_content_codes() {
+        let metadata: Metadata = serde_json::from_str(include_str!(
+            "../../test/data/dst.metadata.response.contents.json"
+        ))
+        .unwrap();
+        let whole: serde_json::Value = serde_json::from_str(include_str!(
+            "../../test/data/dst.data.response.contents.json"
+        ))
+        .unwrap();
+
+        let all = std::collections::BTreeMap::new();
+        let dimensions = super::selection_tags(&metadata, false, &all);
+        assert_eq!(dimensions, vec!["DRIV", "ContentsCode", "Tid"]);
+        let mut one = all.clone();
+        one.insert(
+            "ContentsCode".to_string(),
+            vec!["Nyregistrerede personbiler".to_string()],
+        );
+        assert_eq!(
+            super::selection_tags(&metadata, false, &one),
+            vec!["DRIV", "Tid"]
+        );
+
+        // The whole selection split in one part per content code.
+        let part = |code: &str, other: &str, values: serde_json::Value| {
+            let mut part = whole.clone();
+            let dimension = &mut part["dataset"]["dimension"];
+            dimension["size"][1] = 1.into();
+            let category = dimension["ContentsCode"]["category"]
+                .as_object_mut()
+                .unwrap();
+            for key in ["index", "label", "unit"] {
+                category[key].as_object_mut().unwrap().remove(other);
+            }
+            category["index"][code] = 0.into();
+            part["dataset"]["value"] = values;
+            serde_json::from_value::<DatasetContainer>(part).unwrap()
+        };
+        let mut points = vec![];
+        let mut units = std::collections::BTreeMap::new();
+        for response in [
+            part(
+                "BIL51",
+                "BIL51P",
+                serde_json::json!([9800, 10100, 6100, 6300]),
+            ),
+            part(
+                "BIL51P",
+                "BIL51",
+                serde_json::json!([61.6, 61.6, 38.4, 38.4]),
+            ),
+        ] {
+            super::add_part(&mut points, &mut units, &dimensions, response).unwrap();
+        }
+        assert_eq!(units.len(), 2);
+
+        let whole: DatasetContainer = serde_json::from_value(whole).unwrap();
+        let mut expected =
+            DataPoint::from_dimensions_and_data(&whole.dataset.dimension, &whole.dataset.value)
+                .unwrap();
+        let key = |p: &DataPoint| format!("{:?}", p.tags);
+        points.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(points, expected);
+    }
+
+    /// Dimensions of zero to four values each, named D0, D1, ...
+    fn shape() -> impl Strategy<Value = Vec<(String, Vec<String>)>> {
+        proptest::collection::vec(0..5usize, 0..5).prop_map(|sizes| {
+            sizes
+                .into_iter()
+                .enumerate()
+                .map(|(d, size)| {
+                    let values = (0..size).map(|v| format!("D{}-{}", d, v)).collect();
+                    (format!("D{}", d), values)
+                })
+                .collect()
+        })
+    }
+
+    /// A JSON-stat dimension object for `shape`, with category ids in shuffled index order and a
+    /// contents code, as returned by StatBank.
+    fn jsonstat_dimensions(
+        shape: &[(String, Vec<String>)],
+        orders: &[Vec<usize>],
+    ) -> serde_json::Value {
+        let mut ids: Vec<&str> = shape.iter().map(|(id, _)| id.as_str()).collect();
+        ids.insert(ids.len().saturating_sub(1), "ContentsCode");
+        let mut sizes: Vec<usize> = shape.iter().map(|(_, values)| values.len()).collect();
+        sizes.insert(sizes.len().saturating_sub(1), 1);
+        let mut dimension = serde_json::json!({
+            "id": ids,
+            "size": sizes,
+            "role": {"metric": ["ContentsCode"], "time": []},
+            "ContentsCode": {
+                "label": "Indhold",
+                "category": {
+                    "index": {"X": 0},
+                    "label": {"X": "Indhold"},
+                    "unit": {"X": {"base": "antal", "decimals": 0}}
+                }
+            }
+        });
+        for ((id, values), order) in shape.iter().zip(orders) {
+            let code = |n: usize| format!("c{}", order[n]);
+            let index: serde_json::Map<_, _> =
+                (0..values.len()).map(|n| (code(n), n.into())).collect();
+            let label: serde_json::Map<_, _> = values
+                .iter()
+                .enumerate()
+                .map(|(n, v)| (code(n), v.clone().into()))
+                .collect();
+            dimension[id] = serde_json::json!({
+                "label": id,
+                "category": {"index": index, "label": label}
+            });
+        }
+        dimension
+    }
+
+    proptest! {
+        #[test]
+        fn test_join_dimensions_and_data(shape in shape()) {
+            let dimensions: Vec<(&str, Vec<&str>)> = shape
+                .iter()
+                .map(|(id, values)| (id.as_str(), values.iter().map(String::as_str).collect()))
+                .collect();
+            let cells: usize = dimensions.iter().map(|(_, values)| values.len()).product();
+            let data: Vec<Option<f64>> = (0..cells).map(|n| Some(n as f64)).collect();
+
+            let points = DataPoint::join_dimensions_and_data(&dimensions, im::OrdMap::new(), &data);
+            prop_assert_eq!(points.len(), cells);
+            for (n, point) in points.iter().enumerate() {
+                prop_assert_eq!(point.value, Some(n as f64));
+                prop_assert_eq!(point.tags.len(), dimensions.len());
+                // The last dimension varies fastest.
+                let mut rest = n;
+                for (id, values) in dimensions.iter().rev() {
+                    prop_assert_eq!(&point.tags[*id], values[rest % values.len()]);
+                    rest /= values.len();
+                }
+            }
+        }
+
+        #[test]
+        fn test_jsonstat_shapes(
+            (shape, orders) in shape().prop_flat_map(|shape| {
+                let orders: Vec<_> = shape
+                    .iter()
+                    .map(|(_, values)| Just((0..values.len()).collect::<Vec<_>>()).prop_shuffle())
+                    .collect();
+                (Just(shape), orders)
+            })
+        ) {
+            let dimensions: Dimensions =
+                serde_json::from_value(jsonstat_dimensions(&shape, &orders)).unwrap();
+            let cells: usize = shape.iter().map(|(_, values)| values.len()).product();
+            let data: Vec<Option<f64>> = (0..cells).map(|n| Some(n as f64)).collect();
+
+            let joined: Vec<(&str, Vec<&str>)> = shape
+                .iter()
+                .map(|(id, values)| (id.as_str(), values.iter().map(String::as_str).collect()))
+                .collect();
+            let expected: Vec<DataPoint> =
+                DataPoint::join_dimensions_and_data(&joined, im::OrdMap::new(), &data)
+                    .into_iter()
+                    .collect();
+            prop_assert_eq!(DataPoint::from_dimensions_and_data(&dimensions, &data).unwrap(), expected);
+        }
+    }
 }
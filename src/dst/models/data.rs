@@ -10,15 +10,15 @@ pub struct Category {
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Unit {
-    base: String,
-    decimals: u64,
+    pub base: String,
+    pub decimals: u64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CategoryWithUnit {
-    index: BTreeMap<String, u64>,
-    label: BTreeMap<String, String>,
-    unit: BTreeMap<String, Unit>,
+    pub index: BTreeMap<String, u64>,
+    pub label: BTreeMap<String, String>,
+    pub unit: BTreeMap<String, Unit>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -30,7 +30,7 @@ pub struct Dimension {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ContentsCode {
     label: String,
-    category: CategoryWithUnit,
+    pub category: CategoryWithUnit,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -48,14 +48,6 @@ pub struct Dimensions {
     pub role: Role,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct DimensionsStaticKeys {
-    id: Vec<String>,
-    size: Vec<u64>,
-    role: Role,
-}
-
 impl<'de> Deserialize<'de> for Dimensions {
     fn deserialize<D>(deserializer: D) -> Result<Self, <D as Deserializer<'de>>::Error>
     where
@@ -116,7 +108,28 @@ pub struct Dataset {
     pub label: String,
     pub source: String,
     pub updated: String,
-    pub value: Vec<Option<i64>>,
+    #[serde(serialize_with = "serialize_values")]
+    pub value: Vec<Option<f64>>,
+}
+
+/// Writes whole values without a fraction, as StatBank does.
+fn serialize_values<S: Serializer>(
+    values: &[Option<f64>],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let values: Vec<Option<serde_json::Value>> = values
+        .iter()
+        .map(|v| {
+            v.map(|v| {
+                if v.fract() == 0.0 && v.abs() < 1e15 {
+                    (v as i64).into()
+                } else {
+                    v.into()
+                }
+            })
+        })
+        .collect();
+    values.serialize(serializer)
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -134,6 +147,8 @@ pub struct VariableRequest<'a> {
 pub struct DataRequest<'a> {
     pub table: &'a str,
     pub format: String,
+    #[serde(rename = "valuePresentation", skip_serializing_if = "Option::is_none")]
+    pub value_presentation: Option<&'a str>,
     pub variables: Vec<VariableRequest<'a>>,
 }
 
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 mod dst_date_format {
-    use chrono::{DateTime, TimeZone, Utc};
+    use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
     use serde::{self, Deserialize, Deserializer, Serializer};
 
     const FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
@@ -16,7 +16,8 @@ mod dst_date_format {
     pub fn deserialize<'de, D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<DateTime<Utc>, D::Error> {
-        Utc.datetime_from_str(&String::deserialize(deserializer)?, FORMAT)
+        NaiveDateTime::parse_from_str(&String::deserialize(deserializer)?, FORMAT)
+            .map(|date| Utc.from_utc_datetime(&date))
             .map_err(serde::de::Error::custom)
     }
 }
@@ -34,13 +35,13 @@ pub struct Documentation {
     pub url: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Value {
     pub id: String,
     pub text: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Variable {
     pub elimination: bool,
     pub id: String,
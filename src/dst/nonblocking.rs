@@ -0,0 +1,274 @@
+//! An async StatBank client, for fetching the tables of many charts concurrently.
+//!
+//! Requests of all tables share one client, and their futures are driven together on one
+//! runtime, see `fetch_all` in `main.rs`.
+
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::cache::{self, Cache};
+use super::fixtures::Fixtures;
+use super::models::metadata::{Metadata, MetadataRequest};
+use super::retry::{self, Policy};
+use super::{fetch_jsonstat, API};
+use crate::table::TimeSeriesGroup;
+
+pub struct Client {
+    http: reqwest::Client,
+    api: String,
+    policy: Policy,
+    cache: Option<Cache>,
+    fixtures: Option<Fixtures>,
+}
+
+/// A StatBank table fetched as JSONSTAT through a shared `Client`.
+pub struct Table {
+    client: Arc<Client>,
+    table: String,
+    eliminate: bool,
+}
+
+impl Table {
+    /// See `dst::Table::eliminate`.
+    pub fn eliminate(self) -> Table {
+        Table {
+            eliminate: true,
+            ..self
+        }
+    }
+
+    /// Fetches a selection of value texts per variable.
+    pub async fn fetch(
+        &self,
+        field_selector: &BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        self.client
+            .fetch(&self.table, self.eliminate, field_selector)
+            .await
+    }
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client::with_api(API, Policy::default())
+    }
+
+    /// A client for another StatBank compatible API, e.g. a local stand-in in tests.
+    pub fn with_api(api: &str, policy: Policy) -> Client {
+        Client {
+            http: reqwest::Client::builder()
+                .connect_timeout(policy.connect_timeout)
+                .build()
+                .expect("failed to set up an HTTP client"),
+            api: api.trim_end_matches('/').into(),
+            policy,
+            cache: None,
+            fixtures: None,
+        }
+    }
+
+    /// Records every exchange to, or replays them from, a fixture directory.
+    pub fn with_fixtures(self, fixtures: Fixtures) -> Client {
+        Client {
+            fixtures: Some(fixtures),
+            ..self
+        }
+    }
+
+    /// Falls back to responses in `cache` when StatBank fails, see `dst::Table::connect`.
+    pub fn with_cache(self, cache: Cache) -> Client {
+        Client {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// A table whose requests go through this client.
+    pub fn table(self: &Arc<Self>, table: &str) -> Table {
+        Table {
+            client: self.clone(),
+            table: table.into(),
+            eliminate: false,
+        }
+    }
+
+    /// Posts a JSON request with retries, falling back to the cache when it fails.
+    async fn post(
+        &self,
+        url: &str,
+        request: Vec<u8>,
+    ) -> Result<(Vec<u8>, Option<DateTime<Utc>>), failure::Error> {
+        if let Some(replayed) = self.fixtures.as_ref().and_then(|f| f.replay(url, &request)) {
+            return Ok((replayed?, None));
+        }
+
+        let result = retry::nonblocking(&self.policy, url, || async {
+            self.http
+                .post(url)
+                .timeout(self.policy.timeout)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(request.clone())
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await
+        })
+        .await
+        .map(|b| b.to_vec());
+        if let (Some(fixtures), Ok(response)) = (&self.fixtures, &result) {
+            fixtures.record(url, &request, response)?;
+        }
+        cache::or_stale(self.cache.as_ref(), url, &request, result)
+    }
+
+    /// The table's metadata and, if it came from the cache, when it was fetched.
+    pub async fn metadata(
+        &self,
+        table: &str,
+    ) -> Result<(Metadata, Option<DateTime<Utc>>), failure::Error> {
+        let request = serde_json::to_vec(&MetadataRequest { table })?;
+        let (metadata, stale) = self
+            .post(&format!("{}/tableinfo", self.api), request)
+            .await?;
+        Ok((serde_json::from_slice(&metadata)?, stale))
+    }
+
+    /// Fetches a selection like `dst::Table::fetch`, through the same `fetch_jsonstat`.
+    pub async fn fetch(
+        &self,
+        table: &str,
+        eliminate: bool,
+        field_selector: &BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        let (metadata, stale) = self.metadata(table).await?;
+        let url = format!("{}/data", self.api);
+        let post = |request| self.post(&url, request);
+        fetch_jsonstat(table, &metadata, eliminate, field_selector, stale, post)
+            .await?
+            .into_group(&metadata, field_selector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cache::Cache;
+    use super::super::fixtures::Fixtures;
+    use super::super::retry::Policy;
+    use super::super::standin::{Failure, StandIn};
+    use super::Client;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn benzin() -> BTreeMap<String, Vec<String>> {
+        vec![("DRIV".to_string(), vec!["Benzin".to_string()])]
+            .into_iter()
+            .collect()
+    }
+
+    fn policy() -> Policy {
+        Policy {
+            backoff: Duration::from_millis(10),
+            interval: Duration::ZERO,
+            ..Policy::default()
+        }
+    }
+
+    #[test]
+    fn test_retry() {
+        let standin = StandIn::start(
+            Duration::ZERO,
+            vec![Failure::Status(503), Failure::Status(429)],
+        );
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let group = runtime
+            .block_on(Client::with_api(&standin.api, policy()).fetch(
+                "BIL51",
+                false,
+                &BTreeMap::new(),
+            ))
+            .unwrap();
+        assert_eq!(group.len(), 4);
+        assert_eq!(standin.requests(), 4);
+    }
+
+    #[test]
+    fn test_stale_fallback() {
+        let dir = std::env::temp_dir().join(format!("klima.stale.{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let standin = StandIn::start(Duration::ZERO, vec![]);
+        let client = || {
+            Client::with_api(
+                &standin.api,
+                Policy {
+                    attempts: 2,
+                    ..policy()
+                },
+            )
+            .with_cache(Cache::new(&dir))
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let all = BTreeMap::new();
+
+        let fresh = runtime
+            .block_on(client().fetch("BIL51", false, &all))
+            .unwrap();
+        assert_eq!(fresh.stale_since(), None);
+
+        standin.set_down(true);
+        let stale = runtime
+            .block_on(client().fetch("BIL51", false, &all))
+            .unwrap();
+        assert!(stale.stale_since().is_some());
+        assert_eq!(stale.len(), fresh.len());
+
+        // Without a cached response the failure stands.
+        assert!(runtime
+            .block_on(client().fetch("BIL51", false, &benzin()))
+            .is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("klima.replay.{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let standin = StandIn::start(Duration::ZERO, vec![]);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let selectors = [BTreeMap::new(), benzin()];
+        let fetch_all = |client: &Client| {
+            runtime.block_on(async {
+                let mut groups = vec![];
+                for selector in &selectors {
+                    groups.push(client.fetch("BIL51", false, selector).await?);
+                }
+                Ok::<_, failure::Error>(groups)
+            })
+        };
+
+        let recorder =
+            Client::with_api(&standin.api, policy()).with_fixtures(Fixtures::Record(dir.clone()));
+        let recorded = fetch_all(&recorder).unwrap();
+        assert_eq!(standin.requests(), 4);
+        // Both selections share the tableinfo request.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+
+        standin.set_down(true);
+        let replayer =
+            Client::with_api(&standin.api, policy()).with_fixtures(Fixtures::Replay(dir.clone()));
+        let replayed = fetch_all(&replayer).unwrap();
+        assert_eq!(standin.requests(), 4);
+        for (a, b) in recorded.iter().zip(&replayed) {
+            assert_eq!(a.to_json(), b.to_json());
+        }
+
+        assert!(runtime
+            .block_on(replayer.fetch("BIL54", false, &BTreeMap::new()))
+            .is_err());
+        assert_eq!(standin.requests(), 4);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! Retries with exponential backoff, request timeouts and a per-host rate limit for StatBank and Eurostat.
+//!
+//! Timeouts, connection errors, 429 and 5xx responses are retried; anything else fails at once.
+//! Every failed attempt is logged to stderr.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct Policy {
+    /// Attempts per request, including the first.
+    pub attempts: u32,
+    /// Backoff after the first failure, doubled for every further failure.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Time allowed for establishing a connection.
+    pub connect_timeout: Duration,
+    /// Time allowed for a whole buffered request, including reading the response.
+    ///
+    /// Streamed responses, such as BULK downloads, may take much longer to read than any single
+    /// request, so for them it only bounds the wait for the headers and for each further read.
+    pub timeout: Duration,
+    /// Least time between the start of two requests to the same host.
+    pub interval: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            attempts: 5,
+            backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(60),
+            interval: Duration::from_millis(100),
+        }
+    }
+}
+
+impl Policy {
+    /// The wait after the given failed attempt: half the exponential backoff plus up to as much jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+        let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        exponential / 2 + exponential.mul_f64(jitter / 2.0)
+    }
+}
+
+fn retryable(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error
+            .status()
+            .is_some_and(|s| s.is_server_error() || s == reqwest::StatusCode::TOO_MANY_REQUESTS)
+}
+
+/// When each host may next be sent a request, shared by all clients.
+static NEXT: LazyLock<Mutex<HashMap<String, Instant>>> = LazyLock::new(Default::default);
+
+/// Reserves the next slot for the host of `url`, returning how long to wait for it.
+fn reserve(url: &str, interval: Duration) -> Duration {
+    let host = reqwest::Url::parse(url)
+        .map(|u| {
+            format!(
+                "{}:{:?}",
+                u.host_str().unwrap_or(""),
+                u.port_or_known_default()
+            )
+        })
+        .unwrap_or_default();
+    let now = Instant::now();
+    let mut next = NEXT.lock().unwrap();
+    let slot = next.get(&host).map_or(now, |t| (*t).max(now));
+    next.insert(host, slot + interval);
+    slot - now
+}
+
+/// Sends a request with `send`, retrying transient failures according to the policy.
+pub fn blocking<T>(
+    policy: &Policy,
+    url: &str,
+    mut send: impl FnMut() -> Result<T, reqwest::Error>,
+) -> Result<T, failure::Error> {
+    let mut attempt = 1;
+    loop {
+        std::thread::sleep(reserve(url, policy.interval));
+        match send() {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                let wait = next_attempt(policy, url, attempt, &e)?;
+                std::thread::sleep(wait);
+            }
+        }
+        attempt += 1;
+    }
+}
+
+/// Like `blocking`, for async requests.
+pub async fn nonblocking<T, F: Future<Output = Result<T, reqwest::Error>>>(
+    policy: &Policy,
+    url: &str,
+    mut send: impl FnMut() -> F,
+) -> Result<T, failure::Error> {
+    let mut attempt = 1;
+    loop {
+        tokio::time::sleep(reserve(url, policy.interval)).await;
+        match send().await {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                let wait = next_attempt(policy, url, attempt, &e)?;
+                tokio::time::sleep(wait).await;
+            }
+        }
+        attempt += 1;
+    }
+}
+
+/// Logs a failed attempt and returns the backoff before the next, or the error if there is none.
+fn next_attempt(
+    policy: &Policy,
+    url: &str,
+    attempt: u32,
+    error: &reqwest::Error,
+) -> Result<Duration, failure::Error> {
+    if attempt >= policy.attempts || !retryable(error) {
+        eprintln!(
+            "{}: attempt {}/{} failed, giving up: {}",
+            url, attempt, policy.attempts, error
+        );
+        failure::bail!("{} failed after {} attempts: {}", url, attempt, error);
+    }
+    let wait = policy.backoff(attempt);
+    eprintln!(
+        "{}: attempt {}/{} failed, retrying in {:?}: {}",
+        url, attempt, policy.attempts, wait, error
+    );
+    Ok(wait)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reserve, Policy};
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff() {
+        let policy = Policy {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+            ..Policy::default()
+        };
+        for (attempt, base) in [
+            (1, 100),
+            (2, 200),
+            (3, 400),
+            (4, 800),
+            (5, 1000),
+            (40, 1000),
+        ] {
+            let wait = policy.backoff(attempt);
+            assert!(wait >= Duration::from_millis(base / 2), "{:?}", wait);
+            assert!(wait <= Duration::from_millis(base), "{:?}", wait);
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let interval = Duration::from_secs(10);
+        assert_eq!(
+            reserve("http://limited.invalid/v1/data", interval),
+            Duration::ZERO
+        );
+        let second = reserve("http://limited.invalid/v1/tableinfo", interval);
+        assert!(second > Duration::from_secs(9) && second <= interval);
+        assert!(reserve("http://limited.invalid/v1/data", interval) > Duration::from_secs(19));
+        assert_eq!(reserve("http://other.invalid/", interval), Duration::ZERO);
+    }
+}
@@ -0,0 +1,68 @@
+//! Splitting of data requests that would exceed StatBank's cap on cells per response.
+
+/// StatBank rejects non-streaming requests for more cells than this.
+pub const CELL_LIMIT: usize = 1_000_000;
+
+/// A value selection per variable, as variable id and value ids.
+pub type Selection<'a> = Vec<(&'a str, Vec<&'a str>)>;
+
+pub fn cells(selection: &[(&str, Vec<&str>)]) -> usize {
+    selection.iter().map(|(_, values)| values.len()).product()
+}
+
+/// Splits a selection into selections of at most `limit` cells that together cover the same cells.
+///
+/// Time is split first, so every part holds complete periods; then the largest other variable.
+pub fn split<'a>(selection: Selection<'a>, time_id: &str, limit: usize) -> Vec<Selection<'a>> {
+    let total = cells(&selection);
+    if total <= limit {
+        return vec![selection];
+    }
+
+    let (index, _) = selection
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, values))| values.len() > 1)
+        .max_by_key(|(_, (id, values))| (*id == time_id, values.len()))
+        .expect("a selection above the limit has a variable with several values");
+
+    let len = selection[index].1.len();
+    let chunk = (limit / (total / len)).max(1);
+    selection[index]
+        .1
+        .chunks(chunk)
+        .flat_map(|values| {
+            let mut part = selection.clone();
+            part[index].1 = values.to_vec();
+            split(part, time_id, limit)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cells, split};
+
+    #[test]
+    fn test_split() {
+        let times: Vec<String> = (2011..2021).map(|y| y.to_string()).collect();
+        let selection = vec![
+            ("OMRÅDE", vec!["000", "084", "085"]),
+            ("DRIV", vec!["20200", "20205", "20210", "20215"]),
+            ("Tid", times.iter().map(String::as_str).collect()),
+        ];
+        assert_eq!(cells(&selection), 120);
+        assert_eq!(split(selection.clone(), "Tid", 120).len(), 1);
+
+        // Time is split first: 12 cells per period allows 5 periods per request.
+        let parts = split(selection.clone(), "Tid", 60);
+        assert_eq!(parts.len(), 2);
+        assert!(parts.iter().all(|p| p[2].1.len() == 5 && cells(p) == 60));
+
+        // A single period is still too large, so the largest other variable is split as well.
+        let parts = split(selection.clone(), "Tid", 5);
+        assert!(parts.iter().all(|p| cells(p) <= 5));
+        assert_eq!(parts.iter().map(|p| cells(p)).sum::<usize>(), 120);
+        assert!(parts.iter().all(|p| p[2].1.len() == 1 && p[1].1.len() <= 2));
+    }
+}
@@ -0,0 +1,141 @@
+//! A local stand-in for the StatBank API in tests, serving the BIL51 fixtures.
+//!
+//! BULK requests are answered with the BIL51 extract, written line by line with a pause between.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What to do instead of answering a request.
+#[derive(Clone, Debug)]
+pub enum Failure {
+    Status(u16),
+    /// Waits, then closes the connection without a response.
+    Hang(Duration),
+}
+
+pub struct StandIn {
+    pub api: String,
+    pub requests: Arc<AtomicUsize>,
+    /// The most requests that were in flight at once.
+    pub most_in_flight: Arc<AtomicUsize>,
+    down: Arc<AtomicBool>,
+    stream_pause: Arc<Mutex<Duration>>,
+}
+
+impl StandIn {
+    /// Answers every request after `delay`, failing the first requests as listed in `failures`.
+    pub fn start(delay: Duration, failures: Vec<Failure>) -> StandIn {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let standin = StandIn {
+            api: format!("http://{}/v1", listener.local_addr().unwrap()),
+            requests: Default::default(),
+            most_in_flight: Default::default(),
+            down: Default::default(),
+            stream_pause: Default::default(),
+        };
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let (requests, most) = (standin.requests.clone(), standin.most_in_flight.clone());
+        let failures = Arc::new(Mutex::new(failures.into_iter()));
+        let down = standin.down.clone();
+        let stream_pause = standin.stream_pause.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (in_flight, most) = (in_flight.clone(), most.clone());
+                requests.fetch_add(1, Ordering::SeqCst);
+                let failure = if down.load(Ordering::SeqCst) {
+                    Some(Failure::Status(503))
+                } else {
+                    failures.lock().unwrap().next()
+                };
+                let pause = *stream_pause.lock().unwrap();
+                std::thread::spawn(move || {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+
+                    let mut reader = BufReader::new(stream.unwrap());
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+                    let mut length = 0;
+                    loop {
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                        if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+
+                    std::thread::sleep(delay);
+                    if failure.is_none() && String::from_utf8_lossy(&body).contains("\"BULK\"") {
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        let bulk = include_str!("../../test/data/dst.data.response.bil51.bulk.csv");
+                        let mut stream = reader.into_inner();
+                        let _ = write!(
+                            stream,
+                            "HTTP/1.1 200 Stand-in\r\nContent-Type: text/csv\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            bulk.len(),
+                        );
+                        for (i, line) in bulk.split_inclusive('\n').enumerate() {
+                            if i > 0 {
+                                let _ = stream.flush();
+                                std::thread::sleep(pause);
+                            }
+                            let _ = write!(stream, "{}", line);
+                        }
+                        return;
+                    }
+                    let (status, body) = match failure {
+                        Some(Failure::Hang(wait)) => {
+                            std::thread::sleep(wait);
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                            return;
+                        }
+                        Some(Failure::Status(status)) => (status, "{}"),
+                        None if path.ends_with("/tableinfo") => (
+                            200,
+                            include_str!("../../test/data/dst.metadata.response.bil51.json"),
+                        ),
+                        None => (
+                            200,
+                            include_str!("../../test/data/dst.data.response.bil51.large.json"),
+                        ),
+                    };
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let mut stream = reader.into_inner();
+                    // The client may have given up on a slow response already.
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {} Stand-in\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                });
+            }
+        });
+        standin
+    }
+
+    /// Answers every request with 503 until brought back up.
+    pub fn set_down(&self, down: bool) {
+        self.down.store(down, Ordering::SeqCst);
+    }
+
+    /// How long to pause between the lines of each BULK response.
+    pub fn set_stream_pause(&self, pause: Duration) {
+        *self.stream_pause.lock().unwrap() = pause;
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,393 @@
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+use crate::dst::fixtures::Fixtures;
+use crate::dst::models::metadata::{Value, Variable};
+use crate::dst::{retry, DataPoint};
+use crate::jsonstat;
+use crate::table::{Footnote, Provenance, TimeSeriesGroup};
+
+const API: &str = "https://ec.europa.eu/eurostat/api/dissemination/statistics/1.0/data";
+const DATABROWSER: &str = "https://ec.europa.eu/eurostat/databrowser/view";
+
+/// Danish labels for the category codes used on the dashboard, since Eurostat only publishes
+/// English, French and German ones. Other codes keep their English label.
+const DANISH: [(&str, &str); 7] = [
+    ("DK", "Danmark"),
+    ("EU27_2020", "EU-27"),
+    ("I90", "indeks, 1990 = 100"),
+    ("NR", "antal"),
+    ("ELC", "El"),
+    ("TOTX4_MEMONIA", "I alt, inkl. international luftfart"),
+    ("A", "Årlig"),
+];
+
+/// Danish titles for the datasets used on the dashboard.
+const DANISH_TITLES: [(&str, &str); 2] = [
+    ("sdg_13_10", "Nettoudledning af drivhusgasser"),
+    ("road_eqr_carpda", "Nye personbiler efter drivmiddel"),
+];
+
+fn danish(code: &str) -> Option<&'static str> {
+    DANISH
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, text)| *text)
+}
+
+/// Whether a category code is the total of its dimension, e.g. `TOTAL` in `mot_nrg` or
+/// `TOTX4_MEMONIA` in `src_crf`, whatever its label says.
+fn is_total(code: &str) -> bool {
+    code.starts_with("TOT")
+}
+
+/// The label of a category, in Danish when there is a translation.
+fn label_of(dimension: &jsonstat::Dimension, code: &str) -> String {
+    danish(code).map_or_else(|| dimension.label_of(code), str::to_string)
+}
+
+/// A dataset in the Eurostat dissemination API, e.g. `sdg_13_10`.
+///
+/// Unlike StatBank, Eurostat filters on category codes (`geo=DK`) rather than their labels.
+pub struct Table {
+    client: reqwest::blocking::Client,
+    policy: retry::Policy,
+    fixtures: Option<Fixtures>,
+    dataset: String,
+}
+
+fn parse_updated(updated: Option<&str>) -> Result<DateTime<Utc>, failure::Error> {
+    let updated = updated.ok_or_else(|| failure::format_err!("dataset has no update time"))?;
+    DateTime::parse_from_rfc3339(updated)
+        .or_else(|_| DateTime::parse_from_str(updated, "%Y-%m-%dT%H:%M:%S%z"))
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|e| failure::format_err!("invalid update time {}: {}", updated, e))
+}
+
+/// Maps a JSON-stat dataset to series keyed by category labels, in Danish where translated,
+/// skipping missing values. Labels of total categories are marked as totals.
+pub fn to_timeseries_group(dataset: &jsonstat::Dataset) -> Result<TimeSeriesGroup, failure::Error> {
+    let time_id = dataset
+        .role("time")
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| "time".to_string());
+    if !dataset.id.contains(&time_id) {
+        failure::bail!("dataset has no time dimension");
+    }
+
+    let mut totals = im::OrdSet::new();
+    let points: Vec<DataPoint> = dataset
+        .observations()?
+        .into_iter()
+        .filter_map(|observation| {
+            let value = observation.value?;
+            let tags = dataset
+                .id
+                .iter()
+                .zip(observation.categories)
+                .map(|(id, category)| {
+                    let text = if *id == time_id {
+                        category.id
+                    } else {
+                        let text = danish(&category.id).map_or(category.label, str::to_string);
+                        if is_total(&category.id) {
+                            totals.insert(text.clone());
+                        }
+                        text
+                    };
+                    (id.clone(), text)
+                })
+                .collect();
+            Some(DataPoint {
+                value: Some(value),
+                tags,
+            })
+        })
+        .collect();
+
+    Ok(TimeSeriesGroup::new(
+        parse_updated(dataset.updated.as_deref())?,
+        DataPoint::to_timeseries(&time_id, points)?,
+    )
+    .with_totals(totals))
+}
+
+/// Describes the dimensions of a JSON-stat dataset in StatBank terms, with codes as ids.
+pub fn to_variables(dataset: &jsonstat::Dataset) -> Result<Vec<Variable>, failure::Error> {
+    dataset.validate()?;
+    let time = dataset.role("time");
+    dataset
+        .id
+        .iter()
+        .map(|id| {
+            let dimension = dataset
+                .dimension
+                .get(id)
+                .ok_or_else(|| failure::format_err!("dataset has no dimension {}", id))?;
+            Ok(Variable {
+                id: id.clone(),
+                text: dimension.label.clone().unwrap_or_else(|| id.clone()),
+                elimination: false,
+                time: time.contains(id) || id == "time",
+                values: dimension
+                    .ids()
+                    .into_iter()
+                    .map(|c| Value {
+                        text: label_of(dimension, &c),
+                        id: c,
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+impl Table {
+    pub fn new(dataset: &str) -> Result<Table, failure::Error> {
+        let policy = retry::Policy::default();
+        Ok(Table {
+            client: reqwest::blocking::Client::builder()
+                .connect_timeout(policy.connect_timeout)
+                .timeout(policy.timeout)
+                .build()?,
+            policy,
+            fixtures: None,
+            dataset: dataset.into(),
+        })
+    }
+
+    /// Records every exchange to, or replays them from, a fixture directory, keyed by the
+    /// dataset and query since Eurostat requests have no body.
+    pub fn with_fixtures(self, fixtures: Fixtures) -> Table {
+        Table {
+            fixtures: Some(fixtures),
+            ..self
+        }
+    }
+
+    pub fn dataset(&self) -> &str {
+        &self.dataset
+    }
+
+    /// The dimensions of the dataset, read from a request for the latest period only.
+    pub fn variables(&self) -> Result<Vec<Variable>, failure::Error> {
+        let dataset = self.get(vec![("lastTimePeriod".to_string(), "1".to_string())])?;
+        to_variables(&dataset)
+    }
+
+    pub fn fetch(
+        &self,
+        code_selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        let mut query = vec![];
+        for (dimension, codes) in &code_selector {
+            query.extend(codes.iter().map(|code| (dimension.clone(), code.clone())));
+        }
+        let dataset = self.get(query)?;
+        self.to_timeseries_group(&dataset, code_selector)
+    }
+
+    /// Maps a response for a selection to series, citing the dataset.
+    pub fn to_timeseries_group(
+        &self,
+        dataset: &jsonstat::Dataset,
+        code_selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        Ok(to_timeseries_group(dataset)?.with_provenance(self.provenance(dataset, code_selector)))
+    }
+
+    /// Cites the dataset, with the unit when a single one is selected.
+    fn provenance(
+        &self,
+        dataset: &jsonstat::Dataset,
+        code_selector: BTreeMap<String, Vec<String>>,
+    ) -> Provenance {
+        let unit = dataset
+            .dimension
+            .get("unit")
+            .and_then(|unit| match &unit.ids()[..] {
+                [id] => Some(label_of(unit, id)),
+                _ => None,
+            });
+        Provenance {
+            source: dataset
+                .source
+                .clone()
+                .unwrap_or_else(|| "Eurostat".to_string()),
+            table: self.dataset.clone(),
+            title: DANISH_TITLES
+                .iter()
+                .find(|(id, _)| *id == self.dataset)
+                .map(|(_, title)| title.to_string())
+                .or_else(|| dataset.label.clone())
+                .unwrap_or_default(),
+            unit: unit.unwrap_or_default(),
+            documentation: Some(format!("{}/{}/default/table", DATABROWSER, self.dataset)),
+            selection: code_selector,
+            fetched: Utc::now(),
+            footnotes: dataset
+                .note
+                .iter()
+                .flatten()
+                .map(|text| Footnote {
+                    text: text.clone(),
+                    mandatory: false,
+                })
+                .collect(),
+        }
+    }
+
+    fn get(&self, query: Vec<(String, String)>) -> Result<jsonstat::Dataset, failure::Error> {
+        let mut query = query;
+        query.push(("format".to_string(), "JSON".to_string()));
+        // Eurostat has no Danish, so labels are English unless translated in `DANISH`.
+        query.push(("lang".to_string(), "EN".to_string()));
+
+        let url = format!("{}/{}", API, self.dataset);
+        let request = serde_json::to_vec(&serde_json::json!({
+            "table": self.dataset,
+            "query": query,
+        }))?;
+        let replayed = self
+            .fixtures
+            .as_ref()
+            .and_then(|f| f.replay(&url, &request));
+        let response = match replayed {
+            Some(response) => response?,
+            None => {
+                let response = retry::blocking(&self.policy, &url, || {
+                    self.client
+                        .get(&url)
+                        .query(&query)
+                        .send()?
+                        .error_for_status()?
+                        .bytes()
+                })?;
+                if let Some(fixtures) = &self.fixtures {
+                    fixtures.record(&url, &request, &response)?;
+                }
+                response.to_vec()
+            }
+        };
+
+        match serde_json::from_slice(&response)? {
+            jsonstat::Response::Dataset(dataset) => Ok(*dataset),
+            _ => failure::bail!("expected a dataset from Eurostat for {}", self.dataset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jsonstat::Response;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_to_timeseries_group() {
+        let dataset = match serde_json::from_str(include_str!(
+            "../../test/data/eurostat.data.response.sdg_13_10.json"
+        ))
+        .unwrap()
+        {
+            Response::Dataset(dataset) => dataset,
+            r => panic!("expected a dataset, got {:?}", r),
+        };
+
+        let variables = super::to_variables(&dataset).unwrap();
+        let time: Vec<&str> = variables
+            .iter()
+            .filter(|v| v.time)
+            .map(|v| v.id.as_str())
+            .collect();
+        assert_eq!(time, vec!["time"]);
+
+        let mut selector = std::collections::BTreeMap::new();
+        selector.insert("geo".to_string(), vec!["DK".to_string()]);
+        let table = super::Table::new("sdg_13_10").unwrap();
+        let provenance = table
+            .to_timeseries_group(&dataset, selector)
+            .unwrap()
+            .provenance()
+            .cloned()
+            .unwrap();
+        assert_eq!(provenance.title, "Nettoudledning af drivhusgasser");
+        assert_eq!(provenance.unit, "indeks, 1990 = 100");
+
+        let mut malformed = dataset.clone();
+        malformed.dimension.remove("geo");
+        assert!(super::to_variables(&malformed).is_err());
+
+        let group = super::to_timeseries_group(&dataset).unwrap();
+        assert_eq!(group.updated().to_rfc3339(), "2021-06-10T21:00:00+00:00");
+        assert_eq!(group.len(), 2);
+
+        let denmark = group
+            .series()
+            .iter()
+            .find(|ts| ts.tags.contains("Danmark"))
+            .unwrap();
+        assert!(denmark.tags.contains("indeks, 1990 = 100"));
+        assert_eq!(denmark.data.len(), 3);
+        assert_eq!(
+            denmark.data[&NaiveDate::from_ymd_opt(2019, 1, 1).unwrap()],
+            70.4
+        );
+
+        // The EU value for 2019 is missing in the fixture and must not turn into a zero.
+        let eu = group
+            .series()
+            .iter()
+            .find(|ts| ts.tags.contains("EU-27"))
+            .unwrap();
+        assert_eq!(eu.data.len(), 2);
+        assert!(!eu
+            .data
+            .contains_key(&NaiveDate::from_ymd_opt(2019, 1, 1).unwrap()));
+    }
+
+    #[test]
+    fn test_replay() {
+        use crate::dst::fixtures::Fixtures;
+
+        let replay = concat!(env!("CARGO_MANIFEST_DIR"), "/test/data/replay");
+        let table = super::Table::new("sdg_13_10")
+            .unwrap()
+            .with_fixtures(Fixtures::Replay(replay.into()));
+        let selector = |codes: &[(&str, &str)]| {
+            let mut selector = std::collections::BTreeMap::<String, Vec<String>>::new();
+            for (dimension, code) in codes {
+                selector
+                    .entry(dimension.to_string())
+                    .or_default()
+                    .push(code.to_string());
+            }
+            selector
+        };
+
+        let dashboard = selector(&[
+            ("geo", "DK"),
+            ("geo", "EU27_2020"),
+            ("unit", "I90"),
+            ("src_crf", "TOTX4_MEMONIA"),
+        ]);
+        assert_eq!(table.fetch(dashboard).unwrap().len(), 2);
+
+        // Totals are marked by their code, whatever their label.
+        let cars = super::Table::new("road_eqr_carpda")
+            .unwrap()
+            .with_fixtures(Fixtures::Replay(replay.into()))
+            .fetch(selector(&[
+                ("geo", "DK"),
+                ("geo", "EU27_2020"),
+                ("unit", "NR"),
+                ("mot_nrg", "ELC"),
+                ("mot_nrg", "TOTAL"),
+            ]))
+            .unwrap();
+        assert!(cars.is_total("Total"));
+        assert_eq!(cars.share("El").unwrap().len(), 2);
+        // Other selections were never recorded and are not sent either.
+        assert!(table.fetch(selector(&[("geo", "DK")])).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//! Comparison of generated output with committed golden files in `test/golden`.
+//!
+//! After an intended change to the output, run the tests with `UPDATE_GOLDEN=1` to rewrite
+//! the golden files, and review their diff before committing.
+
+use std::path::Path;
+
+/// Characters of context shown on either side of the first difference.
+const CONTEXT: usize = 80;
+
+fn excerpt(text: &str, at: usize) -> String {
+    let mut start = at.saturating_sub(CONTEXT);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    text[start..].chars().take(2 * CONTEXT).collect()
+}
+
+pub fn assert_golden(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {}; run the tests with UPDATE_GOLDEN=1 to create it",
+            path.display(),
+            e
+        )
+    });
+    if expected == actual {
+        return;
+    }
+    let mut at = expected
+        .bytes()
+        .zip(actual.bytes())
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    while !expected.is_char_boundary(at) {
+        at -= 1;
+    }
+    panic!(
+        "{} differs from the generated output at line {}:\n   golden: {:?}\ngenerated: {:?}\nrun the tests with UPDATE_GOLDEN=1 to accept the change",
+        path.display(),
+        expected[..at].matches('\n').count() + 1,
+        excerpt(&expected, at),
+        excerpt(actual, at)
+    );
+}
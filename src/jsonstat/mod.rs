@@ -0,0 +1,395 @@
+//! A reader for JSON-stat 2.0 responses, see https://json-stat.org/format/.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Any of the three response classes the format defines.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "class", rename_all = "lowercase")]
+pub enum Response {
+    Dataset(Box<Dataset>),
+    Collection(Collection),
+    Dimension(Box<DimensionResponse>),
+}
+
+/// Category positions, either as an ordered list of ids or as an id to position map.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Index {
+    List(Vec<String>),
+    Map(BTreeMap<String, usize>),
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Unit {
+    pub label: Option<String>,
+    pub decimals: Option<u64>,
+    pub symbol: Option<String>,
+    pub position: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Category {
+    pub index: Option<Index>,
+    pub label: Option<BTreeMap<String, String>>,
+    pub note: Option<BTreeMap<String, Vec<String>>>,
+    pub unit: Option<BTreeMap<String, Unit>>,
+    pub child: Option<BTreeMap<String, Vec<String>>>,
+    pub coordinates: Option<BTreeMap<String, (f64, f64)>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Dimension {
+    pub label: Option<String>,
+    pub category: Category,
+    pub note: Option<Vec<String>>,
+    pub href: Option<String>,
+    pub link: Option<serde_json::Value>,
+    pub extension: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Role {
+    pub time: Option<Vec<String>>,
+    pub geo: Option<Vec<String>>,
+    pub metric: Option<Vec<String>>,
+}
+
+/// Cell values, either dense in row-major order or sparse keyed by position.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Values {
+    Dense(Vec<Option<f64>>),
+    Sparse(BTreeMap<String, Option<f64>>),
+}
+
+impl Default for Values {
+    fn default() -> Self {
+        Values::Dense(vec![])
+    }
+}
+
+/// Cell statuses: one for every cell, one per cell, or sparse keyed by position.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Status {
+    All(String),
+    Dense(Vec<Option<String>>),
+    Sparse(BTreeMap<String, String>),
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Dataset {
+    pub version: Option<String>,
+    pub href: Option<String>,
+    pub label: Option<String>,
+    pub source: Option<String>,
+    pub updated: Option<String>,
+    #[serde(default)]
+    pub id: Vec<String>,
+    #[serde(default)]
+    pub size: Vec<usize>,
+    pub role: Option<Role>,
+    #[serde(default)]
+    pub dimension: BTreeMap<String, Dimension>,
+    #[serde(default)]
+    pub value: Values,
+    pub status: Option<Status>,
+    pub note: Option<Vec<String>>,
+    pub error: Option<serde_json::Value>,
+    pub link: Option<serde_json::Value>,
+    pub extension: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CollectionLink {
+    #[serde(default)]
+    pub item: Vec<Response>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Collection {
+    pub version: Option<String>,
+    pub href: Option<String>,
+    pub label: Option<String>,
+    pub updated: Option<String>,
+    pub link: Option<CollectionLink>,
+    pub note: Option<Vec<String>>,
+    pub extension: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DimensionResponse {
+    pub version: Option<String>,
+    pub href: Option<String>,
+    pub updated: Option<String>,
+    #[serde(flatten)]
+    pub dimension: Dimension,
+}
+
+/// A category of a dimension, with its label falling back to the id.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CategoryRef {
+    pub id: String,
+    pub label: String,
+}
+
+/// A single cell of a dataset, with one category per dimension in `Dataset.id` order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub categories: Vec<CategoryRef>,
+    pub value: Option<f64>,
+    pub status: Option<String>,
+}
+
+impl Dimension {
+    /// Category ids in index order. A dimension with a single category may omit the index.
+    pub fn ids(&self) -> Vec<String> {
+        match &self.category.index {
+            Some(Index::List(ids)) => ids.clone(),
+            Some(Index::Map(map)) => {
+                let mut ids: Vec<(&usize, &String)> = map.iter().map(|(k, v)| (v, k)).collect();
+                ids.sort();
+                ids.into_iter().map(|(_, id)| id.clone()).collect()
+            }
+            None => self
+                .category
+                .label
+                .iter()
+                .flat_map(|labels| labels.keys().cloned())
+                .collect(),
+        }
+    }
+
+    pub fn label_of(&self, id: &str) -> String {
+        self.category
+            .label
+            .as_ref()
+            .and_then(|labels| labels.get(id))
+            .cloned()
+            .unwrap_or_else(|| id.to_string())
+    }
+}
+
+impl Dataset {
+    pub fn len(&self) -> usize {
+        self.size.iter().product()
+    }
+
+    pub fn value_at(&self, position: usize) -> Option<f64> {
+        match &self.value {
+            Values::Dense(values) => values.get(position).cloned().flatten(),
+            Values::Sparse(values) => values.get(&position.to_string()).cloned().flatten(),
+        }
+    }
+
+    pub fn status_at(&self, position: usize) -> Option<String> {
+        match &self.status {
+            None => None,
+            Some(Status::All(status)) => Some(status.clone()),
+            Some(Status::Dense(statuses)) => statuses.get(position).cloned().flatten(),
+            Some(Status::Sparse(statuses)) => statuses.get(&position.to_string()).cloned(),
+        }
+    }
+
+    /// Dimension ids with the given role, e.g. `time`, `geo` or `metric`.
+    pub fn role(&self, name: &str) -> Vec<String> {
+        let role = self.role.as_ref();
+        let ids = match name {
+            "time" => role.and_then(|r| r.time.as_ref()),
+            "geo" => role.and_then(|r| r.geo.as_ref()),
+            "metric" => role.and_then(|r| r.metric.as_ref()),
+            _ => None,
+        };
+        ids.cloned().unwrap_or_default()
+    }
+
+    /// Checks that ids, sizes, dimensions and values describe the same cube.
+    pub fn validate(&self) -> Result<(), failure::Error> {
+        if self.id.len() != self.size.len() {
+            failure::bail!(
+                "dataset has {} dimension ids but {} sizes",
+                self.id.len(),
+                self.size.len()
+            );
+        }
+        for (id, size) in self.id.iter().zip(&self.size) {
+            let dimension = self
+                .dimension
+                .get(id)
+                .ok_or_else(|| failure::format_err!("dataset has no dimension {}", id))?;
+            if let Some(Index::Map(map)) = &dimension.category.index {
+                let mut positions: Vec<usize> = map.values().cloned().collect();
+                positions.sort_unstable();
+                if positions.iter().enumerate().any(|(n, p)| n != *p) {
+                    failure::bail!(
+                        "dimension {} has index positions {:?} rather than 0..{}",
+                        id,
+                        positions,
+                        map.len()
+                    );
+                }
+            }
+            let categories = dimension.ids().len();
+            if categories != *size {
+                failure::bail!(
+                    "dimension {} has {} categories but size {}",
+                    id,
+                    categories,
+                    size
+                );
+            }
+        }
+        if let Values::Dense(values) = &self.value {
+            if values.len() != self.len() {
+                failure::bail!(
+                    "dataset has {} values but its dimensions span {} cells",
+                    values.len(),
+                    self.len()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Every cell of the cube in row-major order, including missing values.
+    pub fn observations(&self) -> Result<Vec<Observation>, failure::Error> {
+        self.validate()?;
+
+        let categories: Vec<Vec<CategoryRef>> = self
+            .id
+            .iter()
+            .map(|id| {
+                let dimension = &self.dimension[id];
+                dimension
+                    .ids()
+                    .into_iter()
+                    .map(|c| CategoryRef {
+                        label: dimension.label_of(&c),
+                        id: c,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok((0..self.len())
+            .map(|position| {
+                let mut rest = position;
+                let mut cell: Vec<CategoryRef> = self
+                    .size
+                    .iter()
+                    .zip(&categories)
+                    .rev()
+                    .map(|(size, categories)| {
+                        let category = categories[rest % size].clone();
+                        rest /= size;
+                        category
+                    })
+                    .collect();
+                cell.reverse();
+                Observation {
+                    categories: cell,
+                    value: self.value_at(position),
+                    status: self.status_at(position),
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Response, Values};
+
+    fn dataset() -> super::Dataset {
+        match serde_json::from_str(include_str!("../../test/data/jsonstat.dataset.json")).unwrap() {
+            Response::Dataset(dataset) => *dataset,
+            r => panic!("expected a dataset, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_dataset() {
+        let dataset = dataset();
+        assert!(matches!(dataset.value, Values::Sparse(_)));
+        assert_eq!(dataset.role("time"), vec!["time"]);
+        assert_eq!(dataset.role("geo"), vec!["geo"]);
+        assert_eq!(dataset.role("metric"), vec!["unit"]);
+        assert_eq!(
+            dataset.dimension["unit"].category.unit.as_ref().unwrap()["THS_T"].decimals,
+            Some(1)
+        );
+        assert_eq!(dataset.note.as_ref().unwrap().len(), 1);
+        assert!(dataset.extension.is_some());
+
+        let observations = dataset.observations().unwrap();
+        assert_eq!(observations.len(), 6);
+
+        let last = observations.last().unwrap();
+        let labels: Vec<&str> = last.categories.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, vec!["Thousand tonnes", "European Union", "2020"]);
+        assert_eq!(last.value, Some(2.5));
+        assert_eq!(last.status, Some("p".to_string()));
+
+        assert_eq!(observations[1].value, None);
+        assert_eq!(observations[1].categories[1].label, "Denmark");
+        assert_eq!(observations[1].categories[1].id, "DK");
+    }
+
+    #[test]
+    fn test_shape_mismatch() {
+        let mut resized = dataset();
+        resized.size[2] = 4;
+        assert!(resized.observations().is_err());
+
+        let mut dataset = super::Dataset {
+            value: Values::Dense(vec![Some(1.0); 5]),
+            ..dataset()
+        };
+        assert!(dataset.validate().is_err());
+        dataset.value = Values::Dense(vec![Some(1.0); 6]);
+        assert!(dataset.validate().is_ok());
+    }
+
+    #[test]
+    fn test_index_positions() {
+        let mut dataset = dataset();
+        let positions = |dk, eu| {
+            let index = vec![("DK".to_string(), dk), ("EU27_2020".to_string(), eu)];
+            Some(super::Index::Map(index.into_iter().collect()))
+        };
+        for (dk, eu) in [(0, 0), (1, 2), (0, 2)] {
+            dataset.dimension.get_mut("geo").unwrap().category.index = positions(dk, eu);
+            assert!(dataset.validate().is_err(), "{} {}", dk, eu);
+        }
+        dataset.dimension.get_mut("geo").unwrap().category.index = positions(1, 0);
+        assert!(dataset.validate().is_ok());
+        assert_eq!(dataset.dimension["geo"].ids(), vec!["EU27_2020", "DK"]);
+    }
+
+    #[test]
+    fn test_collection_and_dimension() {
+        let collection: Response =
+            serde_json::from_str(include_str!("../../test/data/jsonstat.collection.json")).unwrap();
+        match collection {
+            Response::Collection(c) => {
+                assert_eq!(c.link.as_ref().unwrap().item.len(), 2);
+                match &c.link.as_ref().unwrap().item[1] {
+                    Response::Dataset(d) => assert_eq!(d.observations().unwrap().len(), 2),
+                    r => panic!("expected an embedded dataset, got {:?}", r),
+                }
+            }
+            r => panic!("expected a collection, got {:?}", r),
+        }
+
+        let dimension: Response =
+            serde_json::from_str(include_str!("../../test/data/jsonstat.dimension.json")).unwrap();
+        match dimension {
+            Response::Dimension(d) => {
+                assert_eq!(d.dimension.ids(), vec!["DK", "SE", "EU27_2020"]);
+                assert_eq!(d.dimension.label_of("SE"), "Sweden");
+            }
+            r => panic!("expected a dimension, got {:?}", r),
+        }
+    }
+}
@@ -4,23 +4,34 @@ extern crate horrorshow;
 use horrorshow::helper::doctype;
 use horrorshow::Template;
 
+use crate::source::DataSource;
 use crate::table::TimeSeriesGroup;
 use chrono::NaiveDate;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
 use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
 
 mod dst;
+mod eurostat;
+#[cfg(test)]
+mod golden;
+mod jsonstat;
+mod px;
+mod source;
 mod table;
 mod web;
 
-struct TableFetcher {
-    table: dst::Table,
+struct TableFetcher<S> {
+    source: S,
     selector: BTreeMap<String, Vec<String>>,
 }
 
-impl TableFetcher {
-    pub fn new(table: &str) -> TableFetcher {
+impl<S> TableFetcher<S> {
+    pub fn new(source: S) -> TableFetcher<S> {
         TableFetcher {
-            table: dst::Table::new(table).unwrap(),
+            source,
             selector: Default::default(),
         }
     }
@@ -33,80 +44,364 @@ impl TableFetcher {
             .extend(values.iter().map(|s| s.to_string()));
         s
     }
+}
+
+impl<S: DataSource> TableFetcher<S> {
+    pub fn fetch(self) -> Result<TimeSeriesGroup, failure::Error> {
+        self.source.fetch(self.selector)
+    }
+}
+
+impl<S: DataSource + Send + 'static> TableFetcher<S> {
+    /// Fetches on the runtime's blocking pool, so sources without an async client can be
+    /// fetched together with StatBank tables, see `fetch_all`.
+    pub fn fetch_blocking(self) -> Fetch {
+        async move { tokio::task::spawn_blocking(move || self.fetch()).await? }.boxed()
+    }
+}
+
+impl TableFetcher<dst::nonblocking::Table> {
+    pub fn fetch_async(self) -> Fetch {
+        async move { self.source.fetch(&self.selector).await }.boxed()
+    }
+}
+
+/// A table being fetched, see `fetch_all`.
+type Fetch = BoxFuture<'static, Result<TimeSeriesGroup, failure::Error>>;
+
+/// Drives the fetches on the current runtime with at most `concurrency` in flight, returning
+/// the groups in the order of `fetches`.
+async fn fetch_all(
+    fetches: Vec<Fetch>,
+    concurrency: usize,
+) -> Result<Vec<TimeSeriesGroup>, failure::Error> {
+    let mut fetched: Vec<_> = futures::stream::iter(fetches)
+        .enumerate()
+        .map(|(i, fetch)| fetch.map(move |group| (i, group)))
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    fetched.sort_by_key(|(i, _)| *i);
+    fetched.into_iter().map(|(_, group)| group).collect()
+}
+
+impl TableFetcher<dst::Table> {
+    pub fn fetch_parquet(self, path: &Path) -> Result<usize, failure::Error> {
+        self.source.write_parquet(self.selector, path)
+    }
+}
 
-    pub fn fetch(self) -> TimeSeriesGroup {
-        self.table.fetch(self.selector).unwrap()
+/// Reads `VARIABLE=VALUE` arguments into a selector, where repeated variables add values.
+fn selector(args: &[String]) -> Result<BTreeMap<String, Vec<String>>, failure::Error> {
+    let mut selector: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for selection in args {
+        let mut parts = selection.splitn(2, '=');
+        let (key, value) = (parts.next().unwrap(), parts.next());
+        let value =
+            value.ok_or_else(|| failure::format_err!("expected VARIABLE=VALUE: {}", selection))?;
+        selector.entry(key.into()).or_default().push(value.into());
     }
+    Ok(selector)
+}
+
+/// `klima parquet TABLE OUTPUT [VARIABLE=VALUE ...]` writes a raw StatBank selection to Parquet.
+fn parquet(args: &[String]) -> Result<(), failure::Error> {
+    let (table, output) = match args {
+        [table, output, ..] => (table, output),
+        _ => failure::bail!("usage: klima parquet TABLE OUTPUT [VARIABLE=VALUE ...]"),
+    };
+
+    let fetcher = TableFetcher {
+        source: dst::Table::new(table)?.format(dst::Format::Bulk),
+        selector: selector(&args[2..])?,
+    };
+    let rows = fetcher.fetch_parquet(Path::new(output))?;
+    eprintln!("{}: {} rows", output, rows);
+    Ok(())
+}
+
+/// `klima series FILE [VARIABLE=VALUE ...]` prints a selection from a PX, CSV or JSON series
+/// file as CSV, or lists the file's variables when nothing is selected.
+fn series(args: &[String]) -> Result<(), failure::Error> {
+    let path = match args.first() {
+        Some(path) => Path::new(path),
+        None => failure::bail!("usage: klima series FILE [VARIABLE=VALUE ...]"),
+    };
+    let file: Box<dyn DataSource> = match path.extension().and_then(|e| e.to_str()) {
+        Some("px") => Box::new(px::PxFile::open(path)?),
+        _ => Box::new(source::file::File::open(path)?),
+    };
+
+    if args.len() == 1 {
+        println!("{}", file.describe());
+        for variable in file.variables()? {
+            let values: Vec<&str> = variable.values.iter().map(|v| v.text.as_str()).collect();
+            println!("{}: {}", variable.id, values.join(", "));
+        }
+        return Ok(());
+    }
+    print!("{}", file.fetch(selector(&args[1..])?)?.to_csv_long());
+    Ok(())
+}
+
+struct Options {
+    site: web::Site,
+    /// How many StatBank tables to fetch at once.
+    concurrency: usize,
+    /// Directory for StatBank responses, published in place of fresh data when StatBank is down.
+    response_cache: Option<std::path::PathBuf>,
+    /// Record StatBank and Eurostat exchanges to, or replay them from, a fixture directory.
+    fixtures: Option<dst::fixtures::Fixtures>,
+}
+
+fn options(args: &[String]) -> Result<Options, failure::Error> {
+    let mut site = web::Site::default();
+    let mut concurrency = 4;
+    let mut response_cache = None;
+    let mut fixtures = None;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--images" => site.fallback_images = true,
+            "--offline" => site.offline = true,
+            "--strict" => site.strict = true,
+            "--asset-cache" => {
+                let dir = args.next();
+                site.asset_cache = Some(
+                    dir.ok_or_else(|| failure::format_err!("--asset-cache requires a directory"))?
+                        .into(),
+                )
+            }
+            "--response-cache" => {
+                let dir = args.next();
+                response_cache = Some(
+                    dir.ok_or_else(|| {
+                        failure::format_err!("--response-cache requires a directory")
+                    })?
+                    .into(),
+                )
+            }
+            "--record" | "--replay" => {
+                let dir: std::path::PathBuf = args
+                    .next()
+                    .ok_or_else(|| failure::format_err!("{} requires a directory", arg))?
+                    .into();
+                fixtures = Some(if arg == "--record" {
+                    dst::fixtures::Fixtures::Record(dir)
+                } else {
+                    dst::fixtures::Fixtures::Replay(dir)
+                });
+            }
+            "--concurrency" => {
+                concurrency = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| {
+                        failure::format_err!("--concurrency requires a positive number")
+                    })?
+            }
+            flag if flag.starts_with("--") => failure::bail!("unknown flag: {}", flag),
+            dir => site.dir = Some(dir.into()),
+        }
+    }
+    if site.fallback_images && site.dir.is_none() {
+        failure::bail!("--images requires an output directory");
+    }
+    if site.offline && site.dir.is_none() {
+        failure::bail!("--offline requires an output directory");
+    }
+    Ok(Options {
+        site,
+        concurrency,
+        response_cache,
+        fixtures,
+    })
+}
+
+/// The StatBank tables behind the dashboard, in the order of `Dashboard`'s fields, fetched
+/// through `client`.
+fn statbank_tables(
+    client: &Arc<dst::nonblocking::Client>,
+) -> Vec<TableFetcher<dst::nonblocking::Table>> {
+    let co2 = "Drivhusgasser i alt, ekskl. CO2 fra afbrænding af biomasse";
+    let overpost = "Emissioner fra dansk territorium (UNFCCC/UNECE-opgørelsen) (4=(1)÷(2)÷(3))";
+
+    vec![
+        TableFetcher::new(client.table("BIL54"))
+            .select("DRIV", &["El"])
+            .select("OMRÅDE", &["Hele landet"])
+            .select("BRUG", &["I alt"])
+            .select("BILTYPE", &["Køretøjer i alt"]),
+        TableFetcher::new(client.table("BIL51").eliminate()).select("DRIV", &["Benzin", "Diesel"]),
+        TableFetcher::new(client.table("MRO2"))
+            .select("OVERPOST", &[overpost])
+            .select("EMTYPE8", &[co2]),
+    ]
+}
+
+/// The Eurostat datasets behind the dashboard, selected by category code, in the order of
+/// `Dashboard`'s fields after the StatBank ones.
+fn eurostat_tables(
+    fixtures: Option<&dst::fixtures::Fixtures>,
+) -> Result<Vec<TableFetcher<eurostat::Table>>, failure::Error> {
+    let table = |dataset| -> Result<eurostat::Table, failure::Error> {
+        let table = eurostat::Table::new(dataset)?;
+        Ok(match fixtures {
+            Some(fixtures) => table.with_fixtures(fixtures.clone()),
+            None => table,
+        })
+    };
+    Ok(vec![
+        TableFetcher::new(table("sdg_13_10")?)
+            .select("geo", &["DK", "EU27_2020"])
+            .select("unit", &["I90"])
+            .select("src_crf", &["TOTX4_MEMONIA"]),
+        TableFetcher::new(table("road_eqr_carpda")?)
+            .select("geo", &["DK", "EU27_2020"])
+            .select("unit", &["NR"])
+            .select("mot_nrg", &["ELC", "TOTAL"]),
+    ])
+}
+
+/// Every table behind the dashboard, in the order of `Dashboard`'s fields, with StatBank tables
+/// fetched through `client` and Eurostat ones through `fixtures` when given.
+fn tables(
+    client: &Arc<dst::nonblocking::Client>,
+    fixtures: Option<&dst::fixtures::Fixtures>,
+) -> Result<Vec<Fetch>, failure::Error> {
+    Ok(statbank_tables(client)
+        .into_iter()
+        .map(TableFetcher::fetch_async)
+        .chain(
+            eurostat_tables(fixtures)?
+                .into_iter()
+                .map(TableFetcher::fetch_blocking),
+        )
+        .collect())
+}
+
+/// Checks that a StatBank group's totals match their components, which fails the build in
+/// strict mode and is only a warning otherwise. Eurostat groups are left out, as they select a
+/// part and its total to compute shares.
+fn checked(strict: bool, group: TimeSeriesGroup) -> Result<TimeSeriesGroup, failure::Error> {
+    if let Err(e) = group.check_totals() {
+        let table = group.provenance().map_or("", |p| p.table.as_str());
+        if strict {
+            failure::bail!("{}: {}", table, e);
+        }
+        eprintln!("{}: {}", table, e);
+    }
+    Ok(group)
+}
+
+/// Targets for electric cars that no statistics office publishes, kept in `data/`.
+fn electric_car_targets() -> Result<TimeSeriesGroup, failure::Error> {
+    let file = source::file::File::from_json(
+        "elbilmaal",
+        chrono::Utc::now(),
+        include_str!("../data/elbilmaal.json"),
+    )?;
+    TableFetcher::new(file).fetch()
+}
+
+/// The fetched data behind each chart on the page.
+struct Dashboard {
+    electric_cars: TimeSeriesGroup,
+    electric_car_targets: TimeSeriesGroup,
+    oil_cars: TimeSeriesGroup,
+    emissions: TimeSeriesGroup,
+    eu_emissions: TimeSeriesGroup,
+    eu_electric_cars: TimeSeriesGroup,
 }
 
-fn main() {
+/// Renders the dashboard page, writing the charts' companion files to the site.
+fn page(site: &web::Site, data: Dashboard) -> Result<String, failure::Error> {
     let month = chrono::Duration::days(31);
     let year = chrono::Duration::days(366);
 
-    let electric_cars = TableFetcher::new("BIL54")
-        .select("DRIV", &["El"])
-        .select("OMRÅDE", &["Hele landet"])
-        .select("BRUG", &["I alt"])
-        .select("BILTYPE", &["Køretøjer i alt"])
-        .fetch()
-        .sum("Elbiler på vejene i alt")
+    let electric_cars = data
+        .electric_cars
+        .sum("Elbiler på vejene i alt")?
         .future_goal(
             "Vej til Klimarådets 2030 mål på 1+ million elbiler",
             NaiveDate::from_yo_opt(2030, 1).unwrap(),
-            1_000_000,
+            1_000_000.0,
             month,
-        )
+        )?
+        .overlay(data.electric_car_targets)
         .plot(
+            site,
             "electric_cars",
             "Elbiler siden 2011",
             "måned",
-            "samlet antal indregistrerede elbiler",
-        );
+            "indregistrerede elbiler",
+        )?;
 
-    let oil_cars = TableFetcher::new("BIL51")
-        .select("DRIV", &["Benzin", "Diesel"])
-        .fetch()
-        .sum("Ny-registrerede benzin og diesel biler per måned")
+    let oil_cars = data
+        .oil_cars
+        .sum("Ny-registrerede benzin og diesel biler per måned")?
         .future_goal(
             "Vej til 2030 stop for benzin og diesel",
             NaiveDate::from_yo_opt(2030, 1).unwrap(),
-            0,
+            0.0,
             month,
-        )
+        )?
         .plot(
+            site,
             "oil_cars",
             "Nye Benzin og Diesel biler per måned",
             "måned",
             "nye biler per måned",
-        );
+        )?;
 
-    let co2 = "Drivhusgasser i alt, ekskl. CO2 fra afbrænding af biomasse";
-    let overpost = "Emissioner fra dansk territorium (UNFCCC/UNECE-opgørelsen) (4=(1)÷(2)÷(3))";
-
-    let emissions = TableFetcher::new("MRO2")
-        .select("OVERPOST", &[overpost])
-        .select("EMTYPE8", &[co2])
-        .fetch()
-        .sum("Udledninger fra dansk territorium (UNFCCC/UNECE), i alt, ekskl. CO2 fra afbrænding af biomasse")
-        .map(|v| v * 1_000)
-        .future_goal("Vej til 2030 mål", NaiveDate::from_yo_opt(2030, 1).unwrap(), 21_000_000, year)
-        .future_goal("Vej til 2050 mål", NaiveDate::from_yo_opt(2050, 1).unwrap(), 0, year)
+    let emissions = data.emissions.sum("Udledninger fra dansk territorium (UNFCCC/UNECE), i alt, ekskl. CO2 fra afbrænding af biomasse")?
+        .map(|v| v * 1_000.0)
+        .in_unit("ton CO2-ækvivalenter")
+        .event("Klimaloven vedtaget", NaiveDate::from_ymd_opt(2019, 12, 6).unwrap())
+        .future_goal("Vej til 2030 mål", NaiveDate::from_yo_opt(2030, 1).unwrap(), 21_000_000.0, year)?
+        .future_goal("Vej til 2050 mål", NaiveDate::from_yo_opt(2050, 1).unwrap(), 0.0, year)?
         .plot(
+            site,
             "emissions",
             "Drivhusgasudledninger fra dansk territorium",
             "år",
-            "COe ton",
-        );
+            "udledning",
+        )?;
+
+    // Eurostat's notes are in English, so the EU charts only cite the dataset.
+    let eu_emissions = data
+        .eu_emissions
+        .without_common_tags()
+        .unstacked()
+        .without_footnotes()
+        .plot(
+            site,
+            "eu_emissions",
+            "Drivhusgasudledninger i Danmark og EU",
+            "år",
+            "udledning",
+        )?;
+
+    let eu_electric_cars = data
+        .eu_electric_cars
+        .share("El")?
+        .without_common_tags()
+        .unstacked()
+        .without_footnotes()
+        .plot(
+            site,
+            "eu_electric_cars",
+            "Elbilers andel af nye personbiler i Danmark og EU",
+            "år",
+            "andel af nye personbiler",
+        )?;
 
     let html = html! {
           : doctype::HTML;
           html {
             head {
-                link(rel="stylesheet", href="https://cdn.jsdelivr.net/npm/bootstrap@4.5.3/dist/css/bootstrap.min.css") {}
-                script(src = "https://code.jquery.com/jquery-3.5.1.slim.min.js", integrity="sha384-DfXdz2htPH0lsSSs5nCTpuj/zy4C+OGpamoFVy38MVBnE+IbbVYUew+OrCXaRkfj", crossorigin="anonymous") {}
-                script(src = "https://cdn.jsdelivr.net/npm/bootstrap@4.5.3/dist/js/bootstrap.bundle.min.js", integrity="sha384-ho+j7jyWK8fNQe+A12Hb8AhRq26LrZ/JpcUGGOn+Y7RsweNrtN/tE3MoK7ZeZDyx", crossorigin="anonymous") {}
-                script(src = "https://cdnjs.cloudflare.com/ajax/libs/Chart.js/2.9.4/Chart.min.js") {}
-                script(src = "https://cdnjs.cloudflare.com/ajax/libs/chartjs-plugin-annotation/0.5.7/chartjs-plugin-annotation.min.js") {}
+                : web::assets::head(site)
              }
              body {
                 div(class="container") {
@@ -154,10 +449,260 @@ fn main() {
                       : emissions
                     }
                   }
+                  div(class="row") {
+                    div(class="col col-lg-12") {
+                      : eu_emissions
+                    }
+                  }
+                  div(class="row") {
+                    div(class="col col-lg-12") {
+                      : eu_electric_cars
+                    }
+                  }
                 }
              }
             }
     };
 
-    println!("{}", html.into_string().unwrap());
+    Ok(html.into_string()?)
+}
+
+fn main() -> Result<(), failure::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("parquet") => return parquet(&args[1..]),
+        Some("series") => return series(&args[1..]),
+        _ => {}
+    }
+
+    let Options {
+        site,
+        concurrency,
+        response_cache,
+        fixtures,
+    } = options(&args)?;
+    if site.offline {
+        web::assets::vendor(&site)?;
+    }
+
+    let mut client = dst::nonblocking::Client::new();
+    if let Some(dir) = response_cache {
+        client = client.with_cache(dst::cache::Cache::new(&dir));
+    }
+    if let Some(fixtures) = &fixtures {
+        client = client.with_fixtures(fixtures.clone());
+    }
+    let client = Arc::new(client);
+    let fetches = tables(&client, fixtures.as_ref())?;
+    let mut groups = tokio::runtime::Runtime::new()?
+        .block_on(fetch_all(fetches, concurrency))?
+        .into_iter();
+
+    let html = page(
+        &site,
+        Dashboard {
+            electric_cars: checked(site.strict, groups.next().unwrap())?,
+            electric_car_targets: electric_car_targets()?,
+            oil_cars: checked(site.strict, groups.next().unwrap())?,
+            emissions: checked(site.strict, groups.next().unwrap())?,
+            eu_emissions: groups.next().unwrap(),
+            eu_electric_cars: groups.next().unwrap(),
+        },
+    )?;
+    match site.dir {
+        Some(_) => site.write("index.html", html.as_bytes())?,
+        None => println!("{}", html),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checked, fetch_all, page, selector, tables, Dashboard, TableFetcher};
+    use crate::dst::fixtures::Fixtures;
+    use crate::dst::nonblocking::Client;
+    use crate::dst::retry::Policy;
+    use crate::dst::standin::StandIn;
+    use crate::golden::assert_golden;
+    use crate::source::fixture::Fixture;
+    use crate::table::{Provenance, TimeSeries, TimeSeriesGroup};
+    use chrono::{TimeZone, Utc};
+    use horrorshow::Template;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_table_fetcher() {
+        let fixture = Fixture::statbank(
+            include_str!("../test/data/dst.metadata.response.bil51.json"),
+            include_str!("../test/data/dst.data.response.bil51.large.json"),
+        )
+        .unwrap();
+
+        let group = TableFetcher::new(fixture)
+            .select("DRIV", &["Benzin"])
+            .select("EJER", &["Husholdningerne", "Erhvervene"])
+            .fetch()
+            .unwrap()
+            .sum("Benzin")
+            .unwrap();
+        assert_eq!(group.len(), 1);
+        assert_eq!(group.series()[0].data.len(), 3);
+    }
+
+    #[test]
+    fn test_selector() {
+        let args: Vec<String> = vec!["DRIV=Benzin".into(), "DRIV=Diesel".into(), "Tid".into()];
+        assert!(selector(&args).is_err());
+        let selector = selector(&args[..2]).unwrap();
+        assert_eq!(selector["DRIV"], vec!["Benzin", "Diesel"]);
+    }
+
+    #[test]
+    fn test_checked() {
+        let date = chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        let series = |tag: &str, value| TimeSeries::unit(im::OrdSet::unit(tag.into()), date, value);
+        let group = || {
+            TimeSeriesGroup::new(
+                Utc::now(),
+                vec![
+                    series("I alt", 10.0),
+                    series("Benzin", 4.0),
+                    series("Diesel", 5.0),
+                ],
+            )
+        };
+
+        assert_eq!(checked(false, group()).unwrap().len(), 3);
+        assert!(checked(true, group()).is_err());
+    }
+
+    #[test]
+    fn test_contents() {
+        let fixture = || {
+            Fixture::statbank(
+                include_str!("../test/data/dst.metadata.response.contents.json"),
+                include_str!("../test/data/dst.data.response.contents.json"),
+            )
+            .unwrap()
+        };
+
+        let all = || TableFetcher::new(fixture()).fetch().unwrap();
+        assert_eq!(all().len(), 4);
+        assert!(all().sum("Benzin og diesel").is_err());
+
+        let families = all().families();
+        assert_eq!(families.len(), 2);
+        let share = &families[0];
+        assert_eq!(share.len(), 2);
+        assert_eq!(share.series()[0].label(), "Benzin");
+        assert_eq!(share.series()[0].data.values().sum::<f64>(), 123.2);
+        assert_eq!(share.unit_label(), Some("pct."));
+        assert_eq!(share.decimals(), 1);
+
+        // Each measure gets its own chart, with its unit on the axis and its decimals in the table.
+        let html = all()
+            .plot(
+                &Default::default(),
+                "contents",
+                "Nyregistreringer",
+                "måned",
+                "værdi",
+            )
+            .unwrap()
+            .into_string()
+            .unwrap();
+        assert!(html.contains("id=\"contents_1\""));
+        assert!(html.contains("id=\"contents_2\""));
+        assert!(html.contains("værdi, pct."));
+        assert!(html.contains("værdi, antal"));
+        assert!(html.contains("<td>61,6</td>"));
+
+        let count = TableFetcher::new(fixture())
+            .select("ContentsCode", &["Nyregistrerede personbiler"])
+            .fetch()
+            .unwrap()
+            .sum("Benzin og diesel")
+            .unwrap();
+        assert_eq!(count.series()[0].data.values().sum::<f64>(), 32_300.0);
+        assert_eq!(count.unit_label(), Some("antal"));
+        assert_eq!(count.decimals(), 0);
+
+        // The source is cited through the sum, by variable text.
+        let provenance = count.provenance().unwrap();
+        assert_eq!(provenance.table, "BIL51");
+        assert_eq!(
+            provenance.selection["Indhold"],
+            vec!["Nyregistrerede personbiler"]
+        );
+    }
+
+    #[test]
+    fn test_fetch_all() {
+        let standin = StandIn::start(Duration::from_millis(50), vec![]);
+        let policy = Policy {
+            interval: Duration::ZERO,
+            ..Policy::default()
+        };
+        let client = Arc::new(Client::with_api(&standin.api, policy));
+        let fetches = (0..4)
+            .map(|_| {
+                TableFetcher::new(client.table("BIL51").eliminate())
+                    .select("DRIV", &["Benzin"])
+                    .fetch_async()
+            })
+            .collect();
+
+        // A current-thread runtime, so only the async client can keep requests in flight together.
+        let groups = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(fetch_all(fetches, 2))
+            .unwrap();
+
+        assert_eq!(groups.len(), 4);
+        assert!(groups.iter().all(|g| g.len() == 4));
+        assert_eq!(standin.most_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    /// Renders the page from the StatBank and Eurostat exchanges in `test/data/replay`, all of
+    /// which are synthetic since neither API can be reached from the test environment.
+    #[test]
+    fn test_page() {
+        let replay =
+            Fixtures::Replay(concat!(env!("CARGO_MANIFEST_DIR"), "/test/data/replay").into());
+        let client = Arc::new(Client::new().with_fixtures(replay.clone()));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        // Pins the fetch time, so the page does not change from day to day.
+        let fetched = Utc.with_ymd_and_hms(2020, 12, 3, 12, 0, 0).unwrap();
+        let fetches = tables(&client, Some(&replay)).unwrap();
+        let mut groups = runtime
+            .block_on(fetch_all(fetches, 1))
+            .unwrap()
+            .into_iter()
+            .map(|group| {
+                let provenance = group.provenance().cloned().unwrap();
+                group.with_provenance(Provenance {
+                    fetched,
+                    ..provenance
+                })
+            });
+
+        let html = page(
+            &Default::default(),
+            Dashboard {
+                electric_cars: checked(true, groups.next().unwrap()).unwrap(),
+                electric_car_targets: super::electric_car_targets().unwrap(),
+                oil_cars: checked(true, groups.next().unwrap()).unwrap(),
+                emissions: checked(true, groups.next().unwrap()).unwrap(),
+                eu_emissions: groups.next().unwrap(),
+                eu_electric_cars: groups.next().unwrap(),
+            },
+        )
+        .unwrap();
+        assert!(!html.contains("include international aviation"));
+        assert_golden("index.html", &html);
+    }
 }
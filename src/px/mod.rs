@@ -0,0 +1,481 @@
+//! A reader for PC-Axis (`.px`) files as published by the Nordic statistics offices.
+//!
+//! A file is a list of `KEYWORD[lang]("subkey", ...)=value, ...;` entries followed by `DATA`,
+//! with the cells in row-major order over the `STUB` variables and then the `HEADING` ones.
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::dst::models::metadata::{Value, Variable};
+use crate::dst::DataPoint;
+use crate::source;
+use crate::table::{Footnote, Provenance, TimeSeriesGroup};
+
+const MISSING: [&str; 7] = [".", "..", "...", "....", ".....", "......", "-"];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    keyword: String,
+    language: Option<String>,
+    subkeys: Vec<String>,
+    values: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PxFile {
+    /// The table id, e.g. BIL51.
+    pub matrix: Option<String>,
+    pub title: String,
+    pub contents: String,
+    pub units: String,
+    pub source: Option<String>,
+    /// `NOTE` entries, and the mandatory `NOTEX` ones.
+    pub notes: Vec<Footnote>,
+    pub updated: DateTime<Utc>,
+    pub variables: Vec<Variable>,
+    pub data: Vec<Option<f64>>,
+}
+
+/// Whether an entry is `DATA`, which is always the last one.
+fn is_data(statement: &str) -> bool {
+    statement
+        .trim_start()
+        .strip_prefix("DATA")
+        .is_some_and(|rest| rest.trim_start().starts_with('='))
+}
+
+/// Splits the file into entries at semicolons outside of quotes. `DATA` runs to the end of the
+/// file, since its cells may be separated by semicolons too.
+fn statements(input: &str) -> Vec<&str> {
+    let mut statements = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        if i == start && !quoted && is_data(&input[start..]) {
+            statements.push(input[start..].trim().trim_end_matches(';'));
+            break;
+        }
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                statements.push(input[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    statements.into_iter().filter(|s| !s.is_empty()).collect()
+}
+
+/// Parses a comma separated value list. Adjacent quoted strings are one string split over lines.
+fn values(input: &str) -> Vec<String> {
+    let mut values = vec![];
+    let mut chars = input.chars().peekable();
+    let mut current: Option<String> = None;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let s: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                current.get_or_insert_with(String::new).push_str(&s);
+            }
+            ',' => values.extend(current.take()),
+            c if c.is_whitespace() => (),
+            c => {
+                let mut s = c.to_string();
+                let mut depth = if c == '(' { 1 } else { 0 };
+                while let Some(&next) = chars.peek() {
+                    if depth == 0 && (next == ',' || next.is_whitespace()) {
+                        break;
+                    }
+                    match next {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => (),
+                    }
+                    s.push(next);
+                    chars.next();
+                }
+                values.extend(current.replace(s));
+            }
+        }
+    }
+    values.extend(current);
+    values
+}
+
+fn entry(statement: &str) -> Result<Entry, failure::Error> {
+    let split = statement
+        .find('=')
+        .ok_or_else(|| failure::format_err!("expected KEYWORD=VALUE, got {}", statement))?;
+    let (key, value) = (statement[..split].trim(), &statement[split + 1..]);
+
+    let (key, subkeys) = match key.find('(') {
+        Some(i) => (&key[..i], values(key[i + 1..].trim_end_matches(')'))),
+        None => (key, vec![]),
+    };
+    let (keyword, language) = match key.find('[') {
+        Some(i) => (
+            &key[..i],
+            Some(key[i + 1..].trim_end_matches(']').to_string()),
+        ),
+        None => (key, None),
+    };
+
+    let values = if keyword == "DATA" {
+        // Cells are separated by whitespace, commas or semicolons, and missing markers may be
+        // quoted.
+        value
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .filter(|v| !v.is_empty())
+            .map(|v| v.trim_matches('"').to_string())
+            .collect()
+    } else {
+        values(value)
+    };
+
+    Ok(Entry {
+        keyword: keyword.trim().to_string(),
+        language,
+        subkeys,
+        values,
+    })
+}
+
+/// The characters Windows-1252 puts where Latin-1 has C1 control codes, from 0x80 to 0x9f.
+/// The five bytes Windows-1252 leaves undefined keep their Latin-1 meaning.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// The `CODEPAGE` a file declares, read from the raw bytes since keywords are ASCII.
+fn codepage(bytes: &[u8]) -> Option<String> {
+    let keyword = b"CODEPAGE=";
+    let start = bytes.windows(keyword.len()).position(|w| w == keyword)? + keyword.len();
+    let end = start + bytes[start..].iter().position(|b| *b == b';')?;
+    let codepage = String::from_utf8_lossy(&bytes[start..end]);
+    Some(codepage.trim().trim_matches('"').to_lowercase())
+}
+
+/// Decodes a file in the `CODEPAGE` it declares. Files without one are read as UTF-8 when they
+/// are valid UTF-8 and as Latin-1, the PX default, otherwise.
+pub fn decode(bytes: &[u8]) -> Result<String, failure::Error> {
+    let latin1 = || bytes.iter().map(|b| *b as char).collect();
+    let text: String = match codepage(bytes).as_deref() {
+        Some("utf-8") | Some("utf8") => String::from_utf8(bytes.to_vec())?,
+        Some("windows-1252") | Some("cp1252") => bytes
+            .iter()
+            .map(|b| match b {
+                0x80..=0x9f => WINDOWS_1252[(b - 0x80) as usize],
+                b => *b as char,
+            })
+            .collect(),
+        Some("iso-8859-1") | Some("latin1") => latin1(),
+        Some(codepage) => failure::bail!("unsupported CODEPAGE {}", codepage),
+        None => match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) => latin1(),
+        },
+    };
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+pub fn parse(input: &str) -> Result<PxFile, failure::Error> {
+    let entries = statements(input)
+        .into_iter()
+        .map(entry)
+        .collect::<Result<Vec<_>, _>>()?;
+    // Translations repeat every keyword with a language tag; only the default language is kept.
+    let entries: Vec<&Entry> = entries.iter().filter(|e| e.language.is_none()).collect();
+
+    let find = |keyword: &str, subkey: Option<&str>| {
+        entries.iter().find(|e| {
+            e.keyword == keyword
+                && subkey.is_none_or(|s| e.subkeys.first().map(|k| k.as_str()) == Some(s))
+        })
+    };
+    let single = |keyword: &str| find(keyword, None).and_then(|e| e.values.first().cloned());
+    let list =
+        |keyword: &str, subkey: Option<&str>| find(keyword, subkey).map(|e| e.values.clone());
+
+    let names: Vec<String> = list("STUB", None)
+        .unwrap_or_default()
+        .into_iter()
+        .chain(list("HEADING", None).unwrap_or_default())
+        .collect();
+    if names.is_empty() {
+        failure::bail!("PX file has neither STUB nor HEADING");
+    }
+
+    let variables = names
+        .iter()
+        .map(|name| {
+            let texts = list("VALUES", Some(name))
+                .ok_or_else(|| failure::format_err!("no VALUES for variable {}", name))?;
+            let codes = list("CODES", Some(name)).unwrap_or_else(|| texts.clone());
+            if codes.len() != texts.len() {
+                failure::bail!(
+                    "variable {} has {} CODES but {} VALUES",
+                    name,
+                    codes.len(),
+                    texts.len()
+                );
+            }
+            let time = find("TIMEVAL", Some(name)).is_some()
+                || ["tid", "time", "år", "vuosi"].contains(&name.to_lowercase().as_str());
+            if time {
+                // Weeks, half years and other TIMEVAL units cannot be placed on a monthly axis.
+                for code in &codes {
+                    DataPoint::parse_time(code)
+                        .map_err(|e| failure::format_err!("variable {}: {}", name, e))?;
+                }
+            }
+            Ok(Variable {
+                id: find("VARIABLE-CODE", Some(name))
+                    .and_then(|e| e.values.first().cloned())
+                    .unwrap_or_else(|| name.clone()),
+                text: name.clone(),
+                elimination: find("ELIMINATION", Some(name)).is_some_and(|e| e.values != ["NO"]),
+                time,
+                values: codes
+                    .into_iter()
+                    .zip(texts)
+                    .map(|(id, text)| Value { id, text })
+                    .collect(),
+            })
+        })
+        .collect::<Result<Vec<_>, failure::Error>>()?;
+
+    let data: Vec<Option<f64>> = list("DATA", None)
+        .ok_or_else(|| failure::format_err!("PX file has no DATA"))?
+        .iter()
+        .map(|v| {
+            if MISSING.contains(&v.as_str()) {
+                Ok(None)
+            } else {
+                v.parse()
+                    .map(Some)
+                    .map_err(|_| failure::format_err!("invalid number in DATA: {}", v))
+            }
+        })
+        .collect::<Result<_, failure::Error>>()?;
+
+    let cells: usize = variables.iter().map(|v| v.values.len()).product();
+    if cells != data.len() {
+        failure::bail!(
+            "PX variables span {} cells but DATA has {} values",
+            cells,
+            data.len()
+        );
+    }
+
+    let updated = single("LAST-UPDATED")
+        .or_else(|| single("CREATION-DATE"))
+        .ok_or_else(|| {
+            failure::format_err!("PX file has neither LAST-UPDATED nor CREATION-DATE")
+        })?;
+    let updated = NaiveDateTime::parse_from_str(&updated, "%Y%m%d %H:%M")
+        .map(|date| Utc.from_utc_datetime(&date))
+        .map_err(|e| failure::format_err!("invalid date {}: {}", updated, e))?;
+
+    Ok(PxFile {
+        matrix: single("MATRIX"),
+        title: single("TITLE").unwrap_or_default(),
+        contents: single("CONTENTS").unwrap_or_default(),
+        units: single("UNITS").unwrap_or_default(),
+        source: single("SOURCE"),
+        notes: entries
+            .iter()
+            .filter(|e| e.keyword == "NOTE" || e.keyword == "NOTEX")
+            .flat_map(|e| {
+                e.values.iter().map(move |text| Footnote {
+                    text: text.clone(),
+                    mandatory: e.keyword == "NOTEX",
+                })
+            })
+            .collect(),
+        updated,
+        variables,
+        data,
+    })
+}
+
+impl PxFile {
+    pub fn open(path: &Path) -> Result<PxFile, failure::Error> {
+        parse(&decode(&std::fs::read(path)?)?)
+    }
+
+    fn time_id(&self) -> Result<&str, failure::Error> {
+        self.variables
+            .iter()
+            .find(|v| v.time)
+            .map(|v| v.id.as_str())
+            .ok_or_else(|| failure::format_err!("PX file has no time variable"))
+    }
+
+    /// Every present cell, tagged like StatBank: value texts, except codes for time.
+    pub fn data_points(&self) -> Vec<DataPoint> {
+        let mut points = vec![];
+        for (position, value) in self.data.iter().enumerate() {
+            let value = match value {
+                Some(value) => *value,
+                None => continue,
+            };
+            let mut rest = position;
+            let mut tags = im::OrdMap::new();
+            for variable in self.variables.iter().rev() {
+                let v = &variable.values[rest % variable.values.len()];
+                rest /= variable.values.len();
+                let text = if variable.time { &v.id } else { &v.text };
+                tags.insert(variable.id.clone(), text.clone());
+            }
+            points.push(DataPoint {
+                value: Some(value),
+                tags,
+            });
+        }
+        points
+    }
+
+    /// Selects values by text per variable id, keeping all values of unselected variables.
+    pub fn fetch(
+        &self,
+        field_selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        let points = source::select(&self.variables, self.data_points(), &field_selector)?;
+        let provenance = Provenance {
+            source: self.source.clone().unwrap_or_default(),
+            table: self.matrix.clone().unwrap_or_default(),
+            title: self.title.clone(),
+            unit: self.units.clone(),
+            documentation: None,
+            selection: crate::dst::selection(&self.variables, &field_selector),
+            fetched: Utc::now(),
+            footnotes: self.notes.clone(),
+        };
+        Ok(TimeSeriesGroup::new(
+            self.updated,
+            DataPoint::to_timeseries(self.time_id()?, points)?,
+        )
+        .with_provenance(provenance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use std::collections::BTreeMap;
+
+    fn file() -> super::PxFile {
+        super::parse(&super::decode(include_bytes!("../../test/data/px.bil51.px")).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(super::values(r#""a","long ""#), vec!["a", "long "]);
+        assert_eq!(
+            super::values("\"split \"\n\"text\", TLIST(M1, \"2020M01\"-\"2020M03\"), 2"),
+            vec!["split text", "TLIST(M1, \"2020M01\"-\"2020M03\")", "2"]
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let note = |codepage: &str| {
+            let mut bytes = format!("CODEPAGE=\"{}\";\nNOTE=\"", codepage).into_bytes();
+            bytes.extend(b"\x80 \x96 \xd8\";");
+            super::decode(&bytes)
+        };
+        assert!(note("windows-1252").unwrap().contains("€ – Ø"));
+        assert!(note("iso-8859-1").unwrap().contains("\u{80} \u{96} Ø"));
+        assert!(note("utf-8").is_err());
+        assert!(note("ibm850").is_err());
+        assert_eq!(super::decode(b"NOTE=\"\xd8\";").unwrap(), "NOTE=\"Ø\";");
+        assert_eq!(
+            super::decode("NOTE=\"Ø\";".as_bytes()).unwrap(),
+            "NOTE=\"Ø\";"
+        );
+    }
+
+    #[test]
+    fn test_data_separators() {
+        let px = super::parse(concat!(
+            "CREATION-DATE=\"20210210 08:00\";\n",
+            "DATABASE=\"Statistikbanken\";\n",
+            "STUB=\"DRIV\";\n",
+            "HEADING=\"Tid\";\n",
+            "VALUES(\"DRIV\")=\"Benzin\",\"Diesel\";\n",
+            "VALUES(\"Tid\")=\"2020M01\",\"2020M02\";\n",
+            "DATA=\n6512,7204;\n2101; \"..\";\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            px.data,
+            vec![Some(6512.0), Some(7204.0), Some(2101.0), None]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_period() {
+        let error = super::parse(concat!(
+            "CREATION-DATE=\"20210210 08:00\";\n",
+            "STUB=\"DRIV\";\n",
+            "HEADING=\"Tid\";\n",
+            "VALUES(\"DRIV\")=\"Benzin\";\n",
+            "VALUES(\"Tid\")=\"2020H1\",\"2020H2\";\n",
+            "TIMEVAL(\"Tid\")=TLIST(H1),\"2020H1\",\"2020H2\";\n",
+            "DATA=\n6512 7204;\n",
+        ))
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "variable Tid: unsupported time period: 2020H1"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let px = file();
+        assert_eq!(
+            px.title,
+            "Nyregistrerede personbiler efter ejerforhold, drivmiddel og tid"
+        );
+        assert_eq!(px.units, "antal");
+        assert_eq!(px.notes.len(), 1);
+        assert!(!px.notes[0].mandatory);
+        assert_eq!(px.updated.to_rfc3339(), "2021-02-10T08:00:00+00:00");
+
+        let ids: Vec<&str> = px.variables.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, vec!["EJER", "DRIV", "Tid"]);
+        assert!(px.variables[0].elimination);
+        assert!(px.variables[2].time);
+        assert_eq!(px.variables[1].values[2].text, "Øvrige");
+        assert_eq!(px.data.len(), 12);
+        assert_eq!(px.data[5], None);
+    }
+
+    #[test]
+    fn test_fetch() {
+        let px = file();
+        assert_eq!(px.data_points().len(), 11);
+
+        let mut selector = BTreeMap::new();
+        selector.insert("DRIV".to_string(), vec!["Benzin".to_string()]);
+        let group = px.fetch(selector).unwrap();
+        assert_eq!(group.len(), 2);
+        let sum = group.sum("Benzin").unwrap();
+        let benzin = &sum.series()[0];
+        assert_eq!(
+            benzin.data[&NaiveDate::from_ymd_opt(2020, 2, 1).unwrap()],
+            7204.0 + 1480.0
+        );
+
+        let mut selector = BTreeMap::new();
+        selector.insert("DRIV".to_string(), vec!["Brint".to_string()]);
+        assert!(px.fetch(selector).is_err());
+    }
+}
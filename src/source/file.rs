@@ -0,0 +1,288 @@
+//! Series that are not published anywhere, e.g. scenarios and political targets, kept in files.
+//!
+//! A file holds one row per observation: a `date` column, a `value` column and any number of
+//! tag columns, which become variables that can be selected on just like in StatBank.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::{select, time_id, DataSource};
+use crate::dst::models::metadata::{Value, Variable};
+use crate::dst::DataPoint;
+use crate::table::{Provenance, TimeSeriesGroup};
+
+const DATE: &str = "date";
+const VALUE: &str = "value";
+
+/// The JSON variant: `{"title": ..., "updated": ..., "data": [{"date": ..., "value": ..., ...}]}`.
+#[derive(Deserialize)]
+struct JsonFile {
+    title: Option<String>,
+    updated: Option<DateTime<Utc>>,
+    data: Vec<BTreeMap<String, serde_json::Value>>,
+}
+
+pub struct File {
+    /// The file name, cited as the table the series come from.
+    name: String,
+    title: String,
+    updated: DateTime<Utc>,
+    variables: Vec<Variable>,
+    points: Vec<DataPoint>,
+}
+
+/// Builds the variables and points from rows of column name to cell, skipping empty values.
+fn from_rows(
+    name: String,
+    title: String,
+    updated: DateTime<Utc>,
+    columns: &[String],
+    rows: Vec<BTreeMap<String, String>>,
+) -> Result<File, failure::Error> {
+    if !columns.iter().any(|c| c == DATE) || !columns.iter().any(|c| c == VALUE) {
+        failure::bail!("series file needs a {} and a {} column", DATE, VALUE);
+    }
+
+    let mut variables: Vec<Variable> = columns
+        .iter()
+        .filter(|c| *c != VALUE)
+        .map(|c| Variable {
+            id: c.clone(),
+            text: c.clone(),
+            elimination: false,
+            time: c == DATE,
+            values: vec![],
+        })
+        .collect();
+
+    let mut points = vec![];
+    for (line, row) in rows.into_iter().enumerate() {
+        let value = match row.get(VALUE).map(|v| v.trim()) {
+            None | Some("") => continue,
+            Some(v) => v
+                .parse::<f64>()
+                .map_err(|_| failure::format_err!("row {}: invalid value {}", line + 1, v))?,
+        };
+        let date = row.get(DATE).map(|d| d.trim()).unwrap_or_default();
+        if DataPoint::try_parse_time(date).is_none() {
+            failure::bail!("row {}: invalid date {}", line + 1, date);
+        }
+
+        let mut tags = im::OrdMap::new();
+        for variable in &mut variables {
+            let text = row.get(&variable.id).cloned().unwrap_or_default();
+            if !variable.values.iter().any(|v| v.text == text) {
+                variable.values.push(Value {
+                    id: text.clone(),
+                    text: text.clone(),
+                });
+            }
+            tags.insert(variable.id.clone(), text);
+        }
+        points.push(DataPoint {
+            value: Some(value),
+            tags,
+        });
+    }
+
+    Ok(File {
+        name,
+        title,
+        updated,
+        variables,
+        points,
+    })
+}
+
+impl File {
+    /// Reads a `.csv` or `.json` file; CSV files are dated by their modification time.
+    pub fn open(path: &Path) -> Result<File, failure::Error> {
+        let title = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let updated: DateTime<Utc> = std::fs::metadata(path)?.modified()?.into();
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => File::from_csv(&title, updated, &contents),
+            Some("json") => File::from_json(&title, updated, &contents),
+            _ => failure::bail!("unknown series file type: {}", path.display()),
+        }
+    }
+
+    pub fn from_csv(
+        title: &str,
+        updated: DateTime<Utc>,
+        contents: &str,
+    ) -> Result<File, failure::Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(contents.as_bytes());
+        let columns: Vec<String> = reader.headers()?.iter().map(String::from).collect();
+        let rows = reader
+            .records()
+            .map(|record| {
+                Ok(columns
+                    .iter()
+                    .cloned()
+                    .zip(record?.iter().map(String::from))
+                    .collect())
+            })
+            .collect::<Result<Vec<_>, csv::Error>>()?;
+        from_rows(
+            format!("{}.csv", title),
+            title.into(),
+            updated,
+            &columns,
+            rows,
+        )
+    }
+
+    /// Reads the JSON variant, where `title` and `updated` in the file take precedence.
+    pub fn from_json(
+        title: &str,
+        updated: DateTime<Utc>,
+        contents: &str,
+    ) -> Result<File, failure::Error> {
+        let file: JsonFile = serde_json::from_str(contents)?;
+        let mut columns: Vec<String> = vec![];
+        let rows = file
+            .data
+            .into_iter()
+            .map(|record| {
+                record
+                    .into_iter()
+                    .map(|(column, cell)| {
+                        if !columns.contains(&column) {
+                            columns.push(column.clone());
+                        }
+                        let cell = match cell {
+                            serde_json::Value::Null => String::new(),
+                            serde_json::Value::String(s) => s,
+                            other => other.to_string(),
+                        };
+                        (column, cell)
+                    })
+                    .collect()
+            })
+            .collect();
+        from_rows(
+            format!("{}.json", title),
+            file.title.unwrap_or_else(|| title.into()),
+            file.updated.unwrap_or(updated),
+            &columns,
+            rows,
+        )
+    }
+}
+
+impl DataSource for File {
+    fn describe(&self) -> String {
+        self.title.clone()
+    }
+
+    fn variables(&self) -> Result<Vec<Variable>, failure::Error> {
+        Ok(self.variables.clone())
+    }
+
+    fn fetch(
+        &self,
+        selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        let points = select(&self.variables, self.points.clone(), &selector)?;
+        let provenance = Provenance {
+            source: "Fil".to_string(),
+            table: self.name.clone(),
+            title: self.title.clone(),
+            unit: String::new(),
+            documentation: None,
+            selection: selector,
+            fetched: self.updated,
+            footnotes: vec![],
+        };
+        Ok(TimeSeriesGroup::new(
+            self.updated,
+            DataPoint::to_timeseries(time_id(&self.variables)?, points)?,
+        )
+        .with_provenance(provenance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::File;
+    use crate::source::DataSource;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_csv() {
+        let updated = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let file = File::from_csv(
+            "goals",
+            updated,
+            include_str!("../../test/data/file.goals.csv"),
+        )
+        .unwrap();
+        assert_eq!(file.describe(), "goals");
+
+        let variables = file.variables().unwrap();
+        let ids: Vec<&str> = variables.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, vec!["kilde", "scenarie", "date"]);
+        assert!(variables[2].time);
+
+        let mut selector = BTreeMap::new();
+        selector.insert("scenarie".to_string(), vec!["Elbiler".to_string()]);
+        let group = file.fetch(selector.clone()).unwrap();
+        assert_eq!(group.updated(), updated);
+        assert_eq!(group.len(), 2);
+        let provenance = group.provenance().unwrap();
+        assert_eq!(provenance.table, "goals.csv");
+        assert_eq!(provenance.selection, selector);
+        let regeringen = group
+            .series()
+            .iter()
+            .find(|ts| ts.tags.contains("Regeringen"))
+            .unwrap();
+        assert_eq!(regeringen.data.len(), 1);
+        assert_eq!(
+            regeringen.data[&NaiveDate::from_ymd_opt(2030, 1, 1).unwrap()],
+            775_000.0
+        );
+
+        selector.insert("kilde".to_string(), vec!["Folketinget".to_string()]);
+        assert!(file.fetch(selector).is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let updated = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        let file = File::from_json(
+            "goals",
+            updated,
+            include_str!("../../test/data/file.goals.json"),
+        )
+        .unwrap();
+        assert_eq!(file.describe(), "Klimamål for drivhusgasudledninger");
+
+        let group = file.fetch(BTreeMap::new()).unwrap();
+        assert_eq!(group.updated().to_rfc3339(), "2021-03-01T12:00:00+00:00");
+        assert_eq!(group.len(), 2);
+        let neutral = group
+            .series()
+            .iter()
+            .find(|ts| ts.tags.contains("Klimaneutralitet"))
+            .unwrap();
+        assert_eq!(neutral.data.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let updated = Utc::now();
+        assert!(File::from_csv("x", updated, "date,amount\n2020,1\n").is_err());
+        assert!(File::from_csv("x", updated, "date,value\n2020,many\n").is_err());
+        assert!(File::from_csv("x", updated, "date,value\nsoon,1\n").is_err());
+    }
+}
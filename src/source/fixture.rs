@@ -0,0 +1,123 @@
+//! Recorded tables that serve as a data source without any network access.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+use super::{select, time_id, DataSource};
+use crate::dst::models::data::DatasetContainer;
+use crate::dst::models::metadata::{Metadata, Variable};
+use crate::dst::{self, DataPoint};
+use crate::table::{Provenance, TimeSeriesGroup, Unit};
+
+/// Serves previously recorded data without any network access, e.g. in tests.
+pub struct Fixture {
+    description: String,
+    updated: DateTime<Utc>,
+    variables: Vec<Variable>,
+    points: Vec<DataPoint>,
+    units: Vec<(String, Unit)>,
+    provenance: Option<Provenance>,
+}
+
+impl Fixture {
+    pub fn new(
+        description: &str,
+        updated: DateTime<Utc>,
+        variables: Vec<Variable>,
+        points: Vec<DataPoint>,
+    ) -> Fixture {
+        Fixture {
+            description: description.into(),
+            updated,
+            variables,
+            points,
+            units: vec![],
+            provenance: None,
+        }
+    }
+
+    /// Builds a fixture from saved StatBank `tableinfo` and `data` responses.
+    pub fn statbank(metadata: &str, data: &str) -> Result<Fixture, failure::Error> {
+        let metadata: Metadata = serde_json::from_str(metadata)?;
+        let data: DatasetContainer = serde_json::from_str(data)?;
+
+        // Recordings carry no fetch time, so the table's update time stands in.
+        let provenance = dst::provenance(&metadata, &Default::default(), Some(metadata.updated));
+        Ok(Fixture {
+            units: dst::jsonstat_units(&data.dataset.dimension),
+            provenance: Some(provenance),
+            ..Fixture::new(
+                &format!("{}: {}", metadata.id, metadata.text),
+                metadata.updated,
+                metadata.variables,
+                DataPoint::from_dimensions_and_data(&data.dataset.dimension, &data.dataset.value)?,
+            )
+        })
+    }
+}
+
+impl DataSource for Fixture {
+    fn describe(&self) -> String {
+        self.description.clone()
+    }
+
+    fn variables(&self) -> Result<Vec<Variable>, failure::Error> {
+        Ok(self.variables.clone())
+    }
+
+    fn fetch(
+        &self,
+        selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        let points = select(&self.variables, self.points.clone(), &selector)?;
+        let group = TimeSeriesGroup::new(
+            self.updated,
+            DataPoint::to_timeseries(time_id(&self.variables)?, points)?,
+        )
+        .with_units(self.units.clone());
+        Ok(match &self.provenance {
+            Some(provenance) => group.with_provenance(Provenance {
+                selection: dst::selection(&self.variables, &selector),
+                ..provenance.clone()
+            }),
+            None => group,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fixture;
+    use crate::source::DataSource;
+    use std::collections::BTreeMap;
+
+    fn bil51() -> Fixture {
+        Fixture::statbank(
+            include_str!("../../test/data/dst.metadata.response.bil51.json"),
+            include_str!("../../test/data/dst.data.response.bil51.large.json"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fixture() {
+        let fixture = bil51();
+        assert!(fixture.describe().starts_with("BIL51: "));
+        assert_eq!(fixture.variables().unwrap().len(), 3);
+
+        let all = fixture.fetch(BTreeMap::new()).unwrap();
+        assert_eq!(all.len(), 4);
+
+        let mut selector = BTreeMap::new();
+        selector.insert("EJER".to_string(), vec!["Husholdningerne".to_string()]);
+        assert_eq!(fixture.fetch(selector.clone()).unwrap().len(), 2);
+
+        selector.insert("EJER".to_string(), vec!["Ingen".to_string()]);
+        assert!(fixture.fetch(selector.clone()).is_err());
+
+        selector.clear();
+        selector.insert("OMRÅDE".to_string(), vec!["Hele landet".to_string()]);
+        assert!(fixture.fetch(selector).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+pub mod file;
+#[cfg(test)]
+pub mod fixture;
+
+use std::collections::BTreeMap;
+
+use crate::dst::models::metadata::Variable;
+use crate::dst::{self, DataPoint};
+use crate::table::TimeSeriesGroup;
+use crate::{eurostat, px};
+
+/// A provider of statistical tables that charts can be built from.
+///
+/// Selections map a variable id to the values to keep; variables left out keep all values.
+/// StatBank, PX files and fixtures select by value text, Eurostat by category code.
+pub trait DataSource {
+    /// A short human readable description of the table.
+    fn describe(&self) -> String;
+
+    fn variables(&self) -> Result<Vec<Variable>, failure::Error>;
+
+    fn fetch(
+        &self,
+        selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error>;
+}
+
+/// Keeps the data points matching a selection of value texts, rejecting unknown texts.
+pub fn select(
+    variables: &[Variable],
+    points: Vec<DataPoint>,
+    selector: &BTreeMap<String, Vec<String>>,
+) -> Result<Vec<DataPoint>, failure::Error> {
+    for (id, texts) in selector {
+        let variable = variables
+            .iter()
+            .find(|v| &v.id == id)
+            .ok_or_else(|| failure::format_err!("no such variable: {}", id))?;
+        if let Some(text) = texts
+            .iter()
+            .find(|t| !variable.values.iter().any(|v| &v.text == *t))
+        {
+            failure::bail!("no such variable value: {}", text);
+        }
+    }
+
+    Ok(points
+        .into_iter()
+        .filter(|p| {
+            selector
+                .iter()
+                // Metric dimensions with a single content code leave no tag.
+                .all(|(id, texts)| p.tags.get(id).is_none_or(|t| texts.contains(t)))
+        })
+        .collect())
+}
+
+fn time_id(variables: &[Variable]) -> Result<&str, failure::Error> {
+    variables
+        .iter()
+        .find(|v| v.time)
+        .map(|v| v.id.as_str())
+        .ok_or_else(|| failure::format_err!("table has no time variable"))
+}
+
+impl DataSource for dst::Table {
+    fn describe(&self) -> String {
+        format!("{}: {}", self.metadata().id, self.metadata().text)
+    }
+
+    fn variables(&self) -> Result<Vec<Variable>, failure::Error> {
+        Ok(self.metadata().variables.clone())
+    }
+
+    fn fetch(
+        &self,
+        selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        dst::Table::fetch(self, selector)
+    }
+}
+
+impl<S: DataSource + ?Sized> DataSource for Box<S> {
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+
+    fn variables(&self) -> Result<Vec<Variable>, failure::Error> {
+        (**self).variables()
+    }
+
+    fn fetch(
+        &self,
+        selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        (**self).fetch(selector)
+    }
+}
+
+impl DataSource for eurostat::Table {
+    fn describe(&self) -> String {
+        format!("Eurostat {}", self.dataset())
+    }
+
+    fn variables(&self) -> Result<Vec<Variable>, failure::Error> {
+        eurostat::Table::variables(self)
+    }
+
+    fn fetch(
+        &self,
+        selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        eurostat::Table::fetch(self, selector)
+    }
+}
+
+impl DataSource for px::PxFile {
+    fn describe(&self) -> String {
+        match &self.matrix {
+            Some(matrix) => format!("{}: {}", matrix, self.contents),
+            None => self.title.clone(),
+        }
+    }
+
+    fn variables(&self) -> Result<Vec<Variable>, failure::Error> {
+        Ok(self.variables.clone())
+    }
+
+    fn fetch(
+        &self,
+        selector: BTreeMap<String, Vec<String>>,
+    ) -> Result<TimeSeriesGroup, failure::Error> {
+        px::PxFile::fetch(self, selector)
+    }
+}
@@ -1,21 +1,317 @@
 use crate::web;
 use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::ops::Add;
 
+/// Whether a category text denotes an aggregate of the other categories, e.g. "I alt" or "Hele landet".
+pub fn is_total(text: &str) -> bool {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    let phrase = |phrase: &[&str]| words.windows(phrase.len()).any(|w| w == phrase);
+    phrase(&["i", "alt"])
+        || phrase(&["hele", "landet"])
+        || phrase(&["total"])
+        || phrase(&["totalt"])
+}
+
+/// How far a total may be from the sum of its components, relative to the total.
+const TOLERANCE: f64 = 1e-9;
+
+/// Rounds a value to a number of decimals, e.g. to the precision of its unit.
+pub fn round(value: f64, decimals: u64) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (value * scale).round() / scale
+}
+
+/// A marker drawn on top of a chart, independent of the plotted series.
+#[derive(Clone, Debug)]
+pub enum Annotation {
+    /// Horizontal line at a target value to reach by a date, e.g. "1 million EVs by 2030".
+    Goal {
+        label: String,
+        value: f64,
+        date: NaiveDate,
+    },
+    /// Vertical line at a single date, e.g. a policy decision.
+    Event { label: String, date: NaiveDate },
+    /// Shaded region between two dates, e.g. the forecast towards a goal.
+    Forecast { from: NaiveDate, to: NaiveDate },
+}
+
+/// The unit of a measure, e.g. "antal" with no decimals or "pct." with one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unit {
+    pub base: String,
+    pub decimals: u64,
+}
+
+/// A note from the source about its data, which its terms may require to be shown with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Footnote {
+    pub text: String,
+    pub mandatory: bool,
+}
+
+/// Where a group's data comes from, cited under its chart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Provenance {
+    /// The publisher, e.g. "Danmarks Statistik".
+    pub source: String,
+    /// The table or dataset id, e.g. "BIL54".
+    pub table: String,
+    pub title: String,
+    pub unit: String,
+    pub documentation: Option<String>,
+    /// The selected values per variable, as requested from the source.
+    pub selection: BTreeMap<String, Vec<String>>,
+    /// When the data was fetched from the source.
+    pub fetched: DateTime<Utc>,
+    pub footnotes: Vec<Footnote>,
+}
+
 pub struct TimeSeriesGroup {
     updated: DateTime<Utc>,
     series: Vec<TimeSeries>,
+    annotations: Vec<Annotation>,
+    stacked: bool,
+    stale: Option<DateTime<Utc>>,
+    /// The unit of each measure, by the label of its content code.
+    units: im::OrdMap<String, Unit>,
+    provenance: Option<Provenance>,
+    /// The tags of series overlaid from other sources, see `overlay`.
+    overlays: im::OrdSet<im::OrdSet<String>>,
+    /// Where the overlaid series come from.
+    overlay_provenance: Vec<Provenance>,
+    /// Whether to leave the source's footnotes out under the chart, see `without_footnotes`.
+    footnotes_hidden: bool,
+    /// Tags the source marks as totals, besides those `is_total` recognizes by their text.
+    totals: im::OrdSet<String>,
 }
 
 impl TimeSeriesGroup {
     pub fn new(updated: DateTime<Utc>, series: Vec<TimeSeries>) -> Self {
-        TimeSeriesGroup { updated, series }
+        TimeSeriesGroup {
+            updated,
+            series,
+            annotations: vec![],
+            stacked: true,
+            stale: None,
+            units: im::OrdMap::new(),
+            provenance: None,
+            overlays: im::OrdSet::new(),
+            overlay_provenance: vec![],
+            footnotes_hidden: false,
+            totals: im::OrdSet::new(),
+        }
+    }
+
+    pub fn updated(&self) -> DateTime<Utc> {
+        self.updated
+    }
+
+    /// Marks the data as a fallback fetched at the given time, because the source was unavailable.
+    pub fn stale(self, fetched: Option<DateTime<Utc>>) -> Self {
+        TimeSeriesGroup {
+            stale: fetched,
+            ..self
+        }
+    }
+
+    /// When the data was fetched, if it is a fallback for an unavailable source.
+    pub fn stale_since(&self) -> Option<DateTime<Utc>> {
+        self.stale
+    }
+
+    pub fn with_provenance(self, provenance: Provenance) -> Self {
+        TimeSeriesGroup {
+            provenance: Some(provenance),
+            ..self
+        }
+    }
+
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// The sources to cite: the group's own, then those of its overlays.
+    pub fn citations(&self) -> impl Iterator<Item = &Provenance> {
+        self.provenance.iter().chain(&self.overlay_provenance)
+    }
+
+    /// Draws the series of another source, e.g. targets kept in a file, on top of this group's.
+    /// They are compared with the series rather than added to them, so the group is unstacked.
+    pub fn overlay(self, other: TimeSeriesGroup) -> Self {
+        let mut series = self.series;
+        let mut overlays = self.overlays;
+        for ts in other.series {
+            overlays.insert(ts.tags.clone());
+            series.push(ts);
+        }
+        let mut overlay_provenance = self.overlay_provenance;
+        overlay_provenance.extend(other.provenance);
+        TimeSeriesGroup {
+            series,
+            overlays,
+            overlay_provenance,
+            totals: self.totals.union(other.totals),
+            stacked: false,
+            ..self
+        }
+    }
+
+    /// Marks tags as totals of the other values of their variable, for sources whose labels
+    /// do not say so, e.g. Eurostat's `TOTAL` code.
+    pub fn with_totals(self, totals: impl IntoIterator<Item = String>) -> Self {
+        let mut all = self.totals;
+        all.extend(totals);
+        TimeSeriesGroup {
+            totals: all,
+            ..self
+        }
+    }
+
+    /// Whether a tag denotes a total, by its text or because the source marked it.
+    pub fn is_total(&self, tag: &str) -> bool {
+        self.totals.contains(tag) || is_total(tag)
+    }
+
+    /// Whether the series was overlaid from another source.
+    pub fn is_overlay(&self, ts: &TimeSeries) -> bool {
+        self.overlays.contains(&ts.tags)
+    }
+
+    /// Leaves the source's footnotes out under the chart. Mandatory footnotes are shown
+    /// regardless, with a warning, and fail the build in strict mode.
+    pub fn without_footnotes(self) -> Self {
+        TimeSeriesGroup {
+            footnotes_hidden: true,
+            ..self
+        }
+    }
+
+    pub fn footnotes_hidden(&self) -> bool {
+        self.footnotes_hidden
+    }
+
+    /// Names the unit after a transform changed it, e.g. from 1.000 ton to ton.
+    pub fn in_unit(self, unit: &str) -> Self {
+        TimeSeriesGroup {
+            units: self
+                .units
+                .into_iter()
+                .map(|(label, u)| {
+                    let unit = Unit {
+                        base: unit.to_string(),
+                        ..u
+                    };
+                    (label, unit)
+                })
+                .collect(),
+            provenance: self.provenance.map(|p| Provenance {
+                unit: unit.to_string(),
+                ..p
+            }),
+            ..self
+        }
+    }
+
+    /// Sets the units of the measures, by the label of their content code.
+    pub fn with_units(self, units: impl IntoIterator<Item = (String, Unit)>) -> Self {
+        TimeSeriesGroup {
+            units: units.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// The decimals to show values with: the most of any measure's unit, or when the units are
+    /// unknown, the fewest that show every value exactly, up to three.
+    pub fn decimals(&self) -> u64 {
+        if let Some(decimals) = self.units.values().map(|u| u.decimals).max() {
+            return decimals;
+        }
+        let values = || self.series.iter().flat_map(|ts| ts.data.values());
+        (0..3)
+            .find(|d| values().all(|v| (round(*v, *d) - v).abs() < 1e-9))
+            .unwrap_or(3)
+    }
+
+    /// The unit for the y-axis: that of the group's only measure, or else the table's.
+    pub fn unit_label(&self) -> Option<&str> {
+        match self.units.len() {
+            1 => self.units.values().next().map(|u| u.base.as_str()),
+            _ => self
+                .provenance
+                .as_ref()
+                .map(|p| p.unit.as_str())
+                .filter(|u| !u.is_empty()),
+        }
+    }
+
+    /// The label of the group's measure, if it has exactly one.
+    pub fn measure(&self) -> Option<&str> {
+        match self.units.len() {
+            1 => self.units.keys().next().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// Splits a group with several measures into one group per measure, in the order of their
+    /// labels, dropping the measure's label from the tags.
+    pub fn families(self) -> Vec<TimeSeriesGroup> {
+        if self.units.len() < 2 {
+            return vec![self];
+        }
+        self.units
+            .iter()
+            .map(|(label, unit)| TimeSeriesGroup {
+                series: self
+                    .series
+                    .iter()
+                    .filter(|ts| ts.tags.contains(label) || self.is_overlay(ts))
+                    .map(|ts| ts.clone().with_tags(ts.tags.without(label)))
+                    .collect(),
+                units: im::OrdMap::unit(label.clone(), unit.clone()),
+                updated: self.updated,
+                annotations: self.annotations.clone(),
+                stacked: self.stacked,
+                stale: self.stale,
+                provenance: self.provenance.clone().map(|p| Provenance {
+                    unit: unit.base.clone(),
+                    ..p
+                }),
+                overlays: self.overlays.clone(),
+                overlay_provenance: self.overlay_provenance.clone(),
+                footnotes_hidden: self.footnotes_hidden,
+                totals: self.totals.clone(),
+            })
+            .collect()
+    }
+
+    /// Whether the series add up to a whole and are drawn on top of each other.
+    pub fn stacked(&self) -> bool {
+        self.stacked
+    }
+
+    /// Draws the series as independent lines, e.g. when comparing countries.
+    pub fn unstacked(self) -> Self {
+        TimeSeriesGroup {
+            stacked: false,
+            ..self
+        }
     }
 
     pub fn series(&self) -> &[TimeSeries] {
         &self.series
     }
 
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
     pub fn xs(&self) -> im::OrdSet<NaiveDate> {
         self.series
             .iter()
@@ -24,32 +320,193 @@ impl TimeSeriesGroup {
             .collect()
     }
 
-    pub fn map(self, f: fn(i64) -> i64) -> Self {
+    pub fn map(self, f: fn(f64) -> f64) -> Self {
         TimeSeriesGroup {
-            updated: self.updated,
             series: self.series.into_iter().map(|ts| ts.map(f)).collect(),
+            ..self
         }
     }
 
+    /// Turns each series into its running total, e.g. yearly emissions into a carbon budget.
+    #[allow(dead_code, reason = "no chart shows running totals yet")]
     pub fn accumulative(self) -> Self {
         TimeSeriesGroup {
-            updated: self.updated,
             series: self
                 .series
                 .into_iter()
                 .map(|ts| ts.accumulative())
                 .collect(),
+            ..self
         }
     }
 
-    pub fn sum(self, title: &str) -> Self {
-        TimeSeriesGroup {
-            updated: self.updated,
+    /// Adds up all series, refusing to mix totals with the components they already include,
+    /// or measures with each other.
+    pub fn sum(self, title: &str) -> Result<Self, failure::Error> {
+        let measures: im::OrdSet<String> = self
+            .series
+            .iter()
+            .flat_map(|ts| ts.tags.iter().filter(|t| self.units.contains_key(*t)))
+            .cloned()
+            .collect();
+        if measures.len() > 1 {
+            failure::bail!(
+                "{} would add up different measures: {}",
+                title,
+                measures
+                    .iter()
+                    .map(|m| m.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let totals: im::OrdSet<String> = self
+            .series
+            .iter()
+            .flat_map(|ts| ts.tags.iter().filter(|t| self.is_total(t)).cloned())
+            .collect();
+        if let Some(total) = totals
+            .iter()
+            .find(|t| self.series.iter().any(|ts| !ts.tags.contains(*t)))
+        {
+            failure::bail!(
+                "{} would add the total \"{}\" to its own components; select either the total or the components",
+                title,
+                total
+            );
+        }
+
+        // The sum keeps the unit of its measure, which its tags no longer name.
+        let units = match measures.get_min() {
+            Some(measure) => self
+                .units
+                .get(measure)
+                .map(|unit| im::OrdMap::unit(measure.clone(), unit.clone()))
+                .unwrap_or_default(),
+            None => self.units.clone(),
+        };
+        Ok(TimeSeriesGroup {
+            units,
             series: vec![self
                 .series
                 .into_iter()
                 .fold(TimeSeries::default(), std::ops::Add::add)
                 .with_tags(im::OrdSet::unit(title.to_string()))],
+            ..self
+        })
+    }
+
+    /// Divides each series tagged `part` by its counterpart tagged with a total instead, in percent
+    /// with one decimal, e.g. electric cars out of all new cars per country. Dates missing from
+    /// either are left out.
+    pub fn share(self, part: &str) -> Result<Self, failure::Error> {
+        let mut series = vec![];
+        for total in &self.series {
+            let whole = match total.tags.iter().find(|t| self.is_total(t)) {
+                Some(whole) => whole,
+                None => continue,
+            };
+            let rest = total.tags.without(whole);
+            let counterpart = self
+                .series
+                .iter()
+                .find(|ts| ts.tags == rest.update(part.to_string()))
+                .ok_or_else(|| failure::format_err!("no {} for {}", part, total.label()))?;
+            let data = counterpart
+                .data
+                .iter()
+                .filter_map(|(date, value)| {
+                    let whole = total.data.get(date).filter(|w| **w != 0.0)?;
+                    Some((*date, round(100.0 * value / whole, 1)))
+                })
+                .collect();
+            series.push(TimeSeries::new(rest, data));
+        }
+        if series.is_empty() {
+            failure::bail!("no total to take the share of {} from", part);
+        }
+        let unit = Unit {
+            base: "pct.".to_string(),
+            decimals: 1,
+        };
+        Ok(TimeSeriesGroup {
+            series,
+            units: im::OrdMap::unit(part.to_string(), unit),
+            ..self
+        }
+        .in_unit("pct."))
+    }
+
+    /// Checks that every total series equals the sum of its components present in the group.
+    ///
+    /// A component has the tags of the total with the total tag swapped for one other value.
+    pub fn check_totals(&self) -> Result<(), failure::Error> {
+        for total in &self.series {
+            for tag in total.tags.iter().filter(|t| self.is_total(t)) {
+                let rest = total.tags.without(tag);
+                let components: Vec<&TimeSeries> = self
+                    .series
+                    .iter()
+                    .filter(|ts| {
+                        let extra = ts.tags.clone().relative_complement(rest.clone());
+                        extra.len() == 1
+                            && ts.tags.len() == total.tags.len()
+                            && !ts.tags.contains(tag)
+                    })
+                    .collect();
+                if components.is_empty() {
+                    continue;
+                }
+                for (date, value) in &total.data {
+                    let sum: f64 = components.iter().filter_map(|ts| ts.data.get(date)).sum();
+                    if (sum - value).abs() > TOLERANCE * value.abs().max(1.0) {
+                        failure::bail!(
+                            "{} is {} on {}, but its {} components add up to {}",
+                            total.label(),
+                            value,
+                            date,
+                            components.len(),
+                            sum
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the tags every series shares, leaving only what tells them apart in the legend.
+    pub fn without_common_tags(self) -> Self {
+        let common = self
+            .series
+            .iter()
+            .map(|ts| ts.tags.clone())
+            .reduce(|a, b| a.intersection(b))
+            .unwrap_or_default();
+        TimeSeriesGroup {
+            series: self
+                .series
+                .into_iter()
+                .map(|ts| {
+                    let tags = ts.tags.clone().relative_complement(common.clone());
+                    ts.with_tags(tags)
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Marks a date on the chart with a vertical line, e.g. a policy decision.
+    pub fn event(self, label: &str, date: NaiveDate) -> Self {
+        let mut annotations = self.annotations;
+        annotations.push(Annotation::Event {
+            label: label.to_string(),
+            date,
+        });
+        TimeSeriesGroup {
+            annotations,
+            ..self
         }
     }
 
@@ -57,65 +514,228 @@ impl TimeSeriesGroup {
         self.series.len()
     }
 
+    /// Adds a line from the last data point to `goal` at `date`, failing without data.
+    ///
+    /// The forecast region covers every goal, so chained goals, e.g. for 2030 and then 2050,
+    /// share one region rather than drawing a box each.
     pub fn future_goal(
         self,
         title: &str,
         date: NaiveDate,
-        goal: i64,
+        goal: f64,
         step: chrono::Duration,
-    ) -> Self {
-        let last_date = |ts: &TimeSeries| *ts.data.iter().last().unwrap().0;
-        let final_date = self.series.iter().map(last_date).max().unwrap();
+    ) -> Result<Self, failure::Error> {
+        let last_date = |ts: &TimeSeries| ts.data.keys().next_back().cloned();
+        let final_date = self
+            .series
+            .iter()
+            .filter_map(last_date)
+            .max()
+            .ok_or_else(|| failure::format_err!("no data to draw {} from", title))?;
 
-        let datapoint = |ts: &TimeSeries| *ts.data.get(&final_date).unwrap_or(&0);
-        let final_sum: i64 = self.series.iter().map(datapoint).sum();
+        let datapoint = |ts: &TimeSeries| *ts.data.get(&final_date).unwrap_or(&0.0);
+        let final_sum: f64 = self.series.iter().map(datapoint).sum();
+        let decimals = self.decimals();
 
         let mut running_date = final_date;
         let all_days = (date - running_date).num_days();
 
         let mut goal_data = im::OrdMap::new();
         while running_date < date {
-            running_date = (running_date + step).with_day(1).unwrap();
+            // The last step is cut short, so the line always ends at the goal.
+            running_date = (running_date + step)
+                .with_day(1)
+                .ok_or_else(|| failure::format_err!("no month after {}", running_date))?
+                .min(date);
 
             let days_spent = (running_date - final_date).num_days();
-            let progress = ((goal - final_sum) * days_spent) / all_days;
-            goal_data.insert(running_date, final_sum + progress);
+            let progress = (goal - final_sum) * days_spent as f64 / all_days as f64;
+            goal_data.insert(running_date, round(final_sum + progress, decimals));
         }
 
         let tags = im::OrdSet::unit(title.to_string());
         let mut series = self.series;
         series.push(TimeSeries::new(tags, goal_data));
 
-        TimeSeriesGroup {
-            updated: self.updated,
+        let mut annotations = self.annotations;
+        annotations.push(Annotation::Goal {
+            label: title.to_string(),
+            value: goal,
+            date,
+        });
+        let forecast = annotations.iter_mut().find_map(|a| match a {
+            Annotation::Forecast { from, to } => Some((from, to)),
+            _ => None,
+        });
+        match forecast {
+            Some((from, to)) => {
+                *from = (*from).min(final_date);
+                *to = (*to).max(date);
+            }
+            None => annotations.push(Annotation::Forecast {
+                from: final_date,
+                to: date,
+            }),
+        }
+
+        Ok(TimeSeriesGroup {
             series,
+            annotations,
+            ..self
+        })
+    }
+
+    fn csv_long(&self, delimiter: u8) -> String {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(vec![]);
+        writer.write_record(["date", "series", "value"]).unwrap();
+        for ts in &self.series {
+            for (date, value) in &ts.data {
+                writer
+                    .write_record([date.to_string(), ts.label(), value.to_string()])
+                    .unwrap();
+            }
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    fn csv_wide(&self, delimiter: u8, format: impl Fn(f64) -> String) -> String {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(vec![]);
+        let header =
+            std::iter::once("date".to_string()).chain(self.series.iter().map(|ts| ts.label()));
+        writer.write_record(header).unwrap();
+        for date in self.xs() {
+            let values = self
+                .series
+                .iter()
+                .map(|ts| ts.data.get(&date).map(|v| format(*v)).unwrap_or_default());
+            writer
+                .write_record(std::iter::once(date.to_string()).chain(values))
+                .unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    /// One row per date, series and value.
+    pub fn to_csv_long(&self) -> String {
+        self.csv_long(b',')
+    }
+
+    /// One row per date with a column per series.
+    pub fn to_csv_wide(&self) -> String {
+        self.csv_wide(b',', |v| v.to_string())
+    }
+
+    /// Wide CSV the way a Danish Excel opens it: semicolon separated with a UTF-8 byte order mark
+    /// and a decimal comma, without thousands separators.
+    pub fn to_csv_danish(&self) -> String {
+        let decimals = self.decimals() as usize;
+        let csv = self.csv_wide(b';', |v| format!("{:.*}", decimals, v).replace('.', ","));
+        format!("\u{feff}{}", csv)
+    }
+
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Point {
+            date: NaiveDate,
+            value: f64,
+        }
+
+        #[derive(Serialize)]
+        struct Series {
+            label: String,
+            tags: Vec<String>,
+            data: Vec<Point>,
+        }
+
+        #[derive(Serialize)]
+        struct Group {
+            updated: DateTime<Utc>,
+            series: Vec<Series>,
         }
+
+        let group = Group {
+            updated: self.updated,
+            series: self
+                .series
+                .iter()
+                .map(|ts| Series {
+                    label: ts.label(),
+                    tags: ts.tags.iter().cloned().collect(),
+                    data: ts
+                        .data
+                        .iter()
+                        .map(|(date, value)| Point {
+                            date: *date,
+                            value: *value,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&group).unwrap()
     }
 
-    pub fn plot(self, id: &str, title: &str, x: &str, y: &str) -> impl horrorshow::RenderOnce {
-        let y = format!("{} — {}", y, self.updated.naive_utc());
-        web::ChartGraph::bar_plot_html(id.into(), title.into(), x.into(), y, self)
+    /// Plots the group, with one chart per measure when it has several, so that measures in
+    /// different units never share an axis.
+    pub fn plot(
+        self,
+        site: &web::Site,
+        id: &str,
+        title: &str,
+        x: &str,
+        y: &str,
+    ) -> Result<impl horrorshow::RenderOnce, failure::Error> {
+        let families = self.families();
+        let several = families.len() > 1;
+        let charts = families
+            .into_iter()
+            .enumerate()
+            .map(|(n, group)| {
+                let (id, title) = match group.measure().filter(|_| several) {
+                    Some(measure) => (
+                        format!("{}_{}", id, n + 1),
+                        format!("{}: {}", title, measure),
+                    ),
+                    None => (id.to_string(), title.to_string()),
+                };
+                web::ChartGraph::bar_plot_html(site, id, title, x.into(), y.into(), group)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(horrorshow::Concat(charts))
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Debug, PartialEq)]
 pub struct TimeSeries {
     pub tags: im::OrdSet<String>,
-    pub data: im::OrdMap<NaiveDate, i64>,
+    pub data: im::OrdMap<NaiveDate, f64>,
 }
 
 impl TimeSeries {
-    pub fn new(tags: im::OrdSet<String>, data: im::OrdMap<NaiveDate, i64>) -> TimeSeries {
+    pub fn new(tags: im::OrdSet<String>, data: im::OrdMap<NaiveDate, f64>) -> TimeSeries {
         TimeSeries { tags, data }
     }
 
-    pub fn unit(tags: im::OrdSet<String>, date: NaiveDate, value: i64) -> TimeSeries {
+    pub fn unit(tags: im::OrdSet<String>, date: NaiveDate, value: f64) -> TimeSeries {
         TimeSeries {
             tags,
             data: im::OrdMap::unit(date, value),
         }
     }
 
+    /// The tags joined into a single name, as used for legends and column headers.
+    pub fn label(&self) -> String {
+        self.tags
+            .iter()
+            .map(|t| t.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
     pub fn with_tags(self, tags: im::OrdSet<String>) -> Self {
         TimeSeries {
             tags,
@@ -123,8 +743,9 @@ impl TimeSeries {
         }
     }
 
+    #[allow(dead_code, reason = "only used by `TimeSeriesGroup::accumulative`")]
     pub fn accumulative(self) -> Self {
-        let init = (0i64, im::OrdMap::new());
+        let init = (0f64, im::OrdMap::new());
         let (_total, data) = self
             .data
             .into_iter()
@@ -137,7 +758,7 @@ impl TimeSeries {
         }
     }
 
-    pub fn map(self, f: fn(i64) -> i64) -> Self {
+    pub fn map(self, f: fn(f64) -> f64) -> Self {
         TimeSeries {
             tags: self.tags,
             data: self.data.into_iter().map(|(k, v)| (k, f(v))).collect(),
@@ -155,3 +776,332 @@ impl Add for TimeSeries {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{is_total, Annotation, Provenance, TimeSeries, TimeSeriesGroup, Unit};
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use proptest::prelude::*;
+
+    fn group() -> TimeSeriesGroup {
+        let date = |m| NaiveDate::from_ymd_opt(2020, m, 1).unwrap();
+        let tags = |t: &[&str]| t.iter().map(|s| s.to_string()).collect();
+        TimeSeriesGroup::new(
+            Utc.with_ymd_and_hms(2020, 3, 10, 8, 0, 0).unwrap(),
+            vec![
+                TimeSeries::new(
+                    tags(&["Benzin"]),
+                    vec![(date(1), 10.0), (date(2), 1200.0)].into(),
+                ),
+                TimeSeries::new(tags(&["Diesel", "Privat"]), vec![(date(2), 7.0)].into()),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_totals() {
+        assert!(is_total("I alt"));
+        assert!(is_total("Køretøjer i alt"));
+        assert!(is_total("Hele landet"));
+        assert!(!is_total("Ialt-Benzin"));
+        assert!(!is_total("Diesel"));
+
+        let date = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        let series = |tags: &[&str], value| {
+            TimeSeries::unit(tags.iter().map(|s| s.to_string()).collect(), date, value)
+        };
+        let updated = Utc.with_ymd_and_hms(2020, 3, 10, 8, 0, 0).unwrap();
+        let group = |series| TimeSeriesGroup::new(updated, series);
+
+        let mixed = group(vec![
+            series(&["I alt", "Benzin"], 30.0),
+            series(&["Privat", "Benzin"], 10.0),
+            series(&["Erhverv", "Benzin"], 20.0),
+        ]);
+        assert!(mixed.check_totals().is_ok());
+        assert!(mixed.sum("Benzin").is_err());
+
+        let wrong = group(vec![
+            series(&["I alt", "Benzin"], 31.0),
+            series(&["Privat", "Benzin"], 10.0),
+            series(&["Erhverv", "Benzin"], 20.0),
+        ]);
+        assert!(wrong.check_totals().is_err());
+
+        let totals = group(vec![
+            series(&["I alt", "Benzin"], 30.0),
+            series(&["I alt", "Diesel"], 5.0),
+        ]);
+        assert!(totals.check_totals().is_ok());
+        assert_eq!(totals.sum("Biler").unwrap().series()[0].data[&date], 35.0);
+    }
+
+    #[test]
+    fn test_decimals() {
+        assert_eq!(group().decimals(), 0);
+        let shares = group().map(|v| v / 8.0);
+        assert_eq!(shares.decimals(), 3);
+        assert_eq!(shares.series()[1].data.values().next(), Some(&0.875));
+
+        let unit = Unit {
+            base: "pct.".to_string(),
+            decimals: 1,
+        };
+        let pct = group().with_units(vec![("Andel".to_string(), unit)]);
+        assert_eq!(pct.decimals(), 1);
+        assert_eq!(pct.unit_label(), Some("pct."));
+        assert_eq!(super::round(74.349, 1), 74.3);
+    }
+
+    #[test]
+    fn test_future_goal_last_step() {
+        // Yearly steps from June would pass a goal in January.
+        let june = NaiveDate::from_ymd_opt(2020, 6, 1).unwrap();
+        let goal = NaiveDate::from_ymd_opt(2022, 1, 1).unwrap();
+        let updated = Utc.with_ymd_and_hms(2020, 7, 10, 8, 0, 0).unwrap();
+        let tags = im::OrdSet::unit("El".to_string());
+        let group = TimeSeriesGroup::new(updated, vec![TimeSeries::unit(tags, june, 100.0)])
+            .future_goal("Mål", goal, 1000.0, chrono::Duration::days(365))
+            .unwrap();
+
+        let line = &group.series()[1].data;
+        assert_eq!(line.keys().next_back(), Some(&goal));
+        assert_eq!(line[&goal], 1000.0);
+
+        let empty = TimeSeriesGroup::new(updated, vec![]);
+        assert!(empty
+            .future_goal("Mål", goal, 1000.0, chrono::Duration::days(365))
+            .is_err());
+    }
+
+    #[test]
+    fn test_future_goal_annotations() {
+        let year = |y| NaiveDate::from_yo_opt(y, 1).unwrap();
+        let updated = Utc.with_ymd_and_hms(2020, 7, 10, 8, 0, 0).unwrap();
+        let tags = im::OrdSet::unit("Udledning".to_string());
+        let group = TimeSeriesGroup::new(updated, vec![TimeSeries::unit(tags, year(2020), 45.0)])
+            .future_goal("2030", year(2030), 21.0, chrono::Duration::days(366))
+            .unwrap()
+            .future_goal("2050", year(2050), 0.0, chrono::Duration::days(366))
+            .unwrap();
+
+        let goals: Vec<f64> = group
+            .annotations()
+            .iter()
+            .filter_map(|a| match a {
+                Annotation::Goal { value, .. } => Some(*value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(goals, vec![21.0, 0.0]);
+        let forecasts: Vec<(NaiveDate, NaiveDate)> = group
+            .annotations()
+            .iter()
+            .filter_map(|a| match a {
+                Annotation::Forecast { from, to } => Some((*from, *to)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(forecasts, vec![(year(2020), year(2050))]);
+    }
+
+    #[test]
+    fn test_share() {
+        let date = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        let series = |tags: &[&str], value| {
+            TimeSeries::unit(tags.iter().map(|s| s.to_string()).collect(), date, value)
+        };
+        let updated = Utc.with_ymd_and_hms(2020, 3, 10, 8, 0, 0).unwrap();
+        let group = |series| TimeSeriesGroup::new(updated, series);
+
+        let cars = group(vec![
+            series(&["Danmark", "El"], 32_000.0),
+            series(&["Danmark", "I alt"], 198_100.0),
+            series(&["EU", "El"], 540_000.0),
+            series(&["EU", "I alt"], 10_700_000.0),
+        ]);
+        let share = cars.share("El").unwrap();
+        assert_eq!(share.len(), 2);
+        assert_eq!(share.series()[0].label(), "Danmark");
+        assert_eq!(share.series()[0].data[&date], 16.2);
+        assert_eq!(share.series()[1].data[&date], 5.0);
+        assert_eq!(share.unit_label(), Some("pct."));
+
+        let missing = group(vec![series(&["Danmark", "I alt"], 198_100.0)]);
+        assert!(missing.share("El").is_err());
+
+        // Totals need not say so in their label when the source marks them.
+        let marked = || {
+            group(vec![
+                series(&["Danmark", "El"], 32_000.0),
+                series(&["Danmark", "Alle drivmidler"], 198_100.0),
+            ])
+        };
+        assert!(marked().share("El").is_err());
+        let share = marked()
+            .with_totals(vec!["Alle drivmidler".to_string()])
+            .share("El")
+            .unwrap();
+        assert_eq!(share.series()[0].data[&date], 16.2);
+    }
+
+    #[test]
+    fn test_overlay() {
+        let date = NaiveDate::from_ymd_opt(2030, 1, 1).unwrap();
+        let provenance = |table: &str| Provenance {
+            source: "Fil".to_string(),
+            table: table.to_string(),
+            title: String::new(),
+            unit: String::new(),
+            documentation: None,
+            selection: Default::default(),
+            fetched: Utc.with_ymd_and_hms(2020, 12, 4, 0, 0, 0).unwrap(),
+            footnotes: vec![],
+        };
+        let target = TimeSeries::unit(im::OrdSet::unit("Mål".to_string()), date, 775_000.0);
+        let targets = TimeSeriesGroup::new(Utc::now(), vec![target.clone()])
+            .with_provenance(provenance("mål.csv"));
+
+        let group = group()
+            .with_provenance(provenance("BIL54"))
+            .overlay(targets);
+        assert!(!group.stacked());
+        assert_eq!(group.len(), 3);
+        assert!(group.is_overlay(&target));
+        assert!(!group.is_overlay(&group.series()[0]));
+        assert!(group.xs().contains(&date));
+        let tables: Vec<&str> = group.citations().map(|p| p.table.as_str()).collect();
+        assert_eq!(tables, vec!["BIL54", "mål.csv"]);
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            group().to_csv_long(),
+            "date,series,value\n2020-01-01,Benzin,10\n2020-02-01,Benzin,1200\n2020-02-01,\"Diesel,Privat\",7\n"
+        );
+        assert_eq!(
+            group().to_csv_wide(),
+            "date,Benzin,\"Diesel,Privat\"\n2020-01-01,10,\n2020-02-01,1200,7\n"
+        );
+        assert_eq!(
+            group().to_csv_danish(),
+            "\u{feff}date;Benzin;Diesel,Privat\n2020-01-01;10;\n2020-02-01;1200;7\n"
+        );
+
+        let unit = Unit {
+            base: "pct.".to_string(),
+            decimals: 1,
+        };
+        let shares = group()
+            .map(|v| v / 16.14)
+            .with_units(vec![("Andel".to_string(), unit)]);
+        assert_eq!(
+            shares.to_csv_danish(),
+            "\u{feff}date;Benzin;Diesel,Privat\n2020-01-01;0,6;\n2020-02-01;74,3;0,4\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let json: serde_json::Value = serde_json::from_str(&group().to_json()).unwrap();
+        assert_eq!(json["updated"], "2020-03-10T08:00:00Z");
+        assert_eq!(json["series"][1]["label"], "Diesel,Privat");
+        assert_eq!(json["series"][0]["data"][1]["date"], "2020-02-01");
+        assert_eq!(json["series"][0]["data"][1]["value"], 1200.0);
+    }
+
+    fn date() -> impl Strategy<Value = NaiveDate> {
+        (2000..2030, 1u32..=12).prop_map(|(y, m)| NaiveDate::from_ymd_opt(y, m, 1).unwrap())
+    }
+
+    /// Series without totals, so any of them may be summed.
+    fn series(len: std::ops::Range<usize>) -> impl Strategy<Value = TimeSeries> {
+        let tags = vec!["Benzin", "Diesel", "El", "Privat", "Erhverv"];
+        (
+            proptest::sample::subsequence(tags, 0..3),
+            proptest::collection::btree_map(
+                date(),
+                (-1_000_000_000i64..1_000_000_000).prop_map(|v| v as f64),
+                len,
+            ),
+        )
+            .prop_map(|(tags, data)| {
+                TimeSeries::new(
+                    tags.into_iter().map(String::from).collect(),
+                    data.into_iter().collect(),
+                )
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn test_add_commutative(a in series(0..24), b in series(0..24)) {
+            prop_assert_eq!(a.clone() + b.clone(), b + a);
+        }
+
+        #[test]
+        fn test_add_associative(a in series(0..24), b in series(0..24), c in series(0..24)) {
+            prop_assert_eq!(
+                (a.clone() + b.clone()) + c.clone(),
+                a + (b + c)
+            );
+        }
+
+        #[test]
+        fn test_accumulative_differences(ts in series(0..24)) {
+            let accumulated = ts.clone().accumulative();
+            let mut previous = 0.0;
+            let differences: im::OrdMap<NaiveDate, f64> = accumulated
+                .data
+                .iter()
+                .map(|(date, total)| {
+                    let difference = total - previous;
+                    previous = *total;
+                    (*date, difference)
+                })
+                .collect();
+            prop_assert_eq!(accumulated.tags, ts.tags);
+            prop_assert_eq!(differences, ts.data);
+        }
+
+        #[test]
+        fn test_sum(series in proptest::collection::vec(series(0..24), 1..5)) {
+            let updated = Utc.with_ymd_and_hms(2020, 3, 10, 8, 0, 0).unwrap();
+            let sum = TimeSeriesGroup::new(updated, series.clone()).sum("Biler").unwrap();
+            prop_assert_eq!(sum.len(), 1);
+
+            let total = &sum.series()[0];
+            prop_assert_eq!(total.label(), "Biler");
+            let dates: im::OrdSet<NaiveDate> =
+                series.iter().flat_map(|ts| ts.data.keys()).cloned().collect();
+            prop_assert_eq!(total.data.len(), dates.len());
+            for date in dates {
+                let expected: f64 = series.iter().filter_map(|ts| ts.data.get(&date)).sum();
+                prop_assert_eq!(total.data[&date], expected);
+            }
+        }
+
+        #[test]
+        fn test_future_goal(
+            series in proptest::collection::vec(series(1..24), 1..4),
+            goal in (-1_000_000_000i64..1_000_000_000).prop_map(|v| v as f64),
+            (year, month) in (2031..2060, 1u32..=12),
+            step in prop_oneof![Just(31), Just(366)],
+        ) {
+            let updated = Utc.with_ymd_and_hms(2020, 3, 10, 8, 0, 0).unwrap();
+            let date = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+            let final_date = series.iter().filter_map(|ts| ts.data.keys().next_back()).max().cloned().unwrap();
+            let group = TimeSeriesGroup::new(updated, series)
+                .future_goal("Mål", date, goal, chrono::Duration::days(step))
+                .unwrap();
+
+            let line = group.series().last().unwrap();
+            prop_assert_eq!(line.label(), "Mål");
+            prop_assert_eq!(line.data.get_max(), Some(&(date, goal)));
+            prop_assert!(line.data.keys().all(|d| *d > final_date && *d <= date));
+            let is_goal = |a: &Annotation| matches!(a, Annotation::Goal { value, .. } if *value == goal);
+            prop_assert!(group.annotations().iter().any(is_goal));
+        }
+    }
+}
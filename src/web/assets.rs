@@ -0,0 +1,229 @@
+use std::path::Path;
+
+use base64::Engine;
+use horrorshow::prelude::*;
+use sha2::{Digest, Sha384, Sha512};
+
+use super::Site;
+
+enum Kind {
+    Script,
+    Stylesheet,
+}
+
+/// A third-party file used by the page, pinned to a version and its Subresource Integrity hash.
+struct Asset {
+    name: &'static str,
+    url: &'static str,
+    integrity: &'static str,
+    kind: Kind,
+}
+
+const ASSETS: [Asset; 5] = [
+    Asset {
+        name: "bootstrap-4.5.3.min.css",
+        url: "https://cdn.jsdelivr.net/npm/bootstrap@4.5.3/dist/css/bootstrap.min.css",
+        integrity: "sha384-TX8t27EcRE3e/ihU7zmQxVncDAy5uIKz4rEkgIXeMed4M0jlfIDPvg6uqKI2xXr2",
+        kind: Kind::Stylesheet,
+    },
+    Asset {
+        name: "jquery-3.5.1.slim.min.js",
+        url: "https://code.jquery.com/jquery-3.5.1.slim.min.js",
+        integrity: "sha384-DfXdz2htPH0lsSSs5nCTpuj/zy4C+OGpamoFVy38MVBnE+IbbVYUew+OrCXaRkfj",
+        kind: Kind::Script,
+    },
+    Asset {
+        name: "bootstrap-4.5.3.bundle.min.js",
+        url: "https://cdn.jsdelivr.net/npm/bootstrap@4.5.3/dist/js/bootstrap.bundle.min.js",
+        integrity: "sha384-ho+j7jyWK8fNQe+A12Hb8AhRq26LrZ/JpcUGGOn+Y7RsweNrtN/tE3MoK7ZeZDyx",
+        kind: Kind::Script,
+    },
+    Asset {
+        name: "Chart-2.9.4.min.js",
+        url: "https://cdnjs.cloudflare.com/ajax/libs/Chart.js/2.9.4/Chart.min.js",
+        integrity: "sha512-d9xgZrVZpmmQlfonhQUvTR7lMPtO7NkZMkA0ABN3PHCbKA5nqylQ/yWlFAyY6hYgdF1Qh6nYiuADWwKB4C2WSw==",
+        kind: Kind::Script,
+    },
+    Asset {
+        name: "chartjs-plugin-annotation-0.5.7.min.js",
+        url: "https://cdnjs.cloudflare.com/ajax/libs/chartjs-plugin-annotation/0.5.7/chartjs-plugin-annotation.min.js",
+        integrity: "sha512-9hzM/Gfa9KP1hSBlq3/zyNF/dfbcjAYwUTBWYX+xi8fzfAPHL3ILwS1ci0Zu4T4Q5fWWEeFrKGEbcXLZKh9MKg==",
+        kind: Kind::Script,
+    },
+];
+
+const VENDOR_DIR: &str = "vendor";
+
+fn integrity(algorithm: &str, contents: &[u8]) -> Result<String, failure::Error> {
+    let digest = match algorithm {
+        "sha384" => Sha384::digest(contents).to_vec(),
+        "sha512" => Sha512::digest(contents).to_vec(),
+        _ => failure::bail!("unsupported integrity algorithm: {}", algorithm),
+    };
+    Ok(format!(
+        "{}-{}",
+        algorithm,
+        base64::engine::general_purpose::STANDARD.encode(digest)
+    ))
+}
+
+fn verify(asset: &Asset, contents: &[u8]) -> Result<(), failure::Error> {
+    let algorithm = asset.integrity.split('-').next().unwrap();
+    let actual = integrity(algorithm, contents)?;
+    if actual != asset.integrity {
+        failure::bail!(
+            "integrity mismatch for {}: expected {}, got {}",
+            asset.url,
+            asset.integrity,
+            actual
+        );
+    }
+    Ok(())
+}
+
+/// Reads an asset from the local cache, downloading it there first if it is missing.
+fn load(asset: &Asset, cache: Option<&Path>) -> Result<Vec<u8>, failure::Error> {
+    if let Some(path) = cache.map(|dir| dir.join(asset.name)) {
+        if path.exists() {
+            return Ok(std::fs::read(path)?);
+        }
+    }
+
+    let contents = reqwest::blocking::get(asset.url)?
+        .error_for_status()?
+        .bytes()?
+        .to_vec();
+
+    if let Some(dir) = cache {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(asset.name), &contents)?;
+    }
+    Ok(contents)
+}
+
+/// Copies every asset into the output directory after checking it against its pinned hash.
+pub fn vendor(site: &Site) -> Result<(), failure::Error> {
+    vendor_assets(site, &ASSETS)
+}
+
+fn vendor_assets(site: &Site, assets: &[Asset]) -> Result<(), failure::Error> {
+    for asset in assets {
+        let contents = load(asset, site.asset_cache.as_deref())?;
+        verify(asset, &contents)?;
+        site.write(&format!("{}/{}", VENDOR_DIR, asset.name), &contents)?;
+    }
+    Ok(())
+}
+
+/// Stylesheet and script tags for the page head, pointing at the CDNs or the vendored copies.
+/// Only CDN tags carry the integrity hash; vendored copies were checked when they were written.
+pub fn head(site: &Site) -> impl RenderOnce {
+    head_assets(site, &ASSETS)
+}
+
+fn head_assets<'a>(site: &Site, assets: &'a [Asset]) -> impl RenderOnce + 'a {
+    let offline = site.offline;
+    owned_html! {
+        @ for asset in assets {
+            @ if offline {
+                @ if let Kind::Stylesheet = asset.kind {
+                    link(rel="stylesheet", href=format!("{}/{}", VENDOR_DIR, asset.name)) {}
+                } else {
+                    script(src=format!("{}/{}", VENDOR_DIR, asset.name)) {}
+                }
+            } else {
+                @ if let Kind::Stylesheet = asset.kind {
+                    link(rel="stylesheet", href=asset.url, integrity=asset.integrity, crossorigin="anonymous") {}
+                } else {
+                    script(src=asset.url, integrity=asset.integrity, crossorigin="anonymous") {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{head_assets, integrity, vendor_assets, verify, Asset, Kind, ASSETS, VENDOR_DIR};
+    use crate::web::Site;
+
+    const STYLESHEET: &[u8] = b"/* fixture stylesheet */\n";
+    const SCRIPT: &[u8] = b"/* fixture script */\n";
+
+    /// Stand-ins for the CDN files, which are not reachable from the tests.
+    const FIXTURE_ASSETS: [Asset; 2] = [
+        Asset {
+            name: "fixture.css",
+            url: "https://cdn.invalid/fixture.css",
+            integrity: "sha384-BdahnIWEJe9JjWaqCzGBuOSB9NVRUUYYjdjqveTw9s4bttAE8EJWKOTXZXXecU47",
+            kind: Kind::Stylesheet,
+        },
+        Asset {
+            name: "fixture.js",
+            url: "https://cdn.invalid/fixture.js",
+            integrity: "sha512-FE25fpXfM5hcN8IF2nzHvkhrDUMuCdZ0EtMD1fmcPXGXNv0ZSJTmRBZPO361NYie1jwn2+5N2KCMj90OU8sKIg==",
+            kind: Kind::Script,
+        },
+    ];
+
+    #[test]
+    fn test_integrity() {
+        assert_eq!(
+            integrity("sha384", b"").unwrap(),
+            "sha384-OLBgp1GsljhM2TJ+sbHjaiH9txEUvgdDTAzHv2P24donTt6/529l+9Ua0vFImLlb"
+        );
+        assert!(integrity("md5", b"").is_err());
+        assert!(verify(&ASSETS[0], b"tampered").is_err());
+    }
+
+    #[test]
+    fn test_head() {
+        use horrorshow::{html, Template};
+
+        let render = |offline| {
+            let site = Site {
+                offline,
+                ..Site::default()
+            };
+            html! { : head_assets(&site, &FIXTURE_ASSETS) }
+                .into_string()
+                .unwrap()
+        };
+
+        let online = render(false);
+        assert!(online.contains(r#"href="https://cdn.invalid/fixture.css""#));
+        assert!(online.contains(FIXTURE_ASSETS[1].integrity));
+        assert!(online.contains(r#"crossorigin="anonymous""#));
+
+        let offline = render(true);
+        assert!(offline.contains(r#"src="vendor/fixture.js""#));
+        assert!(!offline.contains("integrity"));
+        assert!(!offline.contains("crossorigin"));
+    }
+
+    #[test]
+    fn test_vendor() {
+        let root = std::env::temp_dir().join(format!("klima.vendor.{}", std::process::id()));
+        let cache = root.join("cache");
+        std::fs::create_dir_all(&cache).unwrap();
+        std::fs::write(cache.join("fixture.css"), STYLESHEET).unwrap();
+        std::fs::write(cache.join("fixture.js"), SCRIPT).unwrap();
+
+        let site = Site {
+            dir: Some(root.join("out")),
+            offline: true,
+            asset_cache: Some(cache.clone()),
+            ..Site::default()
+        };
+        vendor_assets(&site, &FIXTURE_ASSETS).unwrap();
+        for asset in &FIXTURE_ASSETS {
+            let path = root.join("out").join(VENDOR_DIR).join(asset.name);
+            let contents = std::fs::read(&path).unwrap();
+            verify(asset, &contents).unwrap();
+        }
+
+        std::fs::write(cache.join("fixture.js"), b"tampered").unwrap();
+        assert!(vendor_assets(&site, &FIXTURE_ASSETS).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use horrorshow::prelude::*;
+
+use super::{format_number, ChartConfig};
+use crate::table::{Annotation, TimeSeriesGroup};
+
+/// One sentence per fact: the latest observation and how far it is from each goal.
+pub fn summary(series: &TimeSeriesGroup) -> String {
+    let decimals = series.decimals();
+    // Unstacked series are compared rather than added up, so each gets its own sentence.
+    if !series.stacked() {
+        return series
+            .series()
+            .iter()
+            .filter_map(|ts| ts.data.iter().next_back().map(|point| (ts.label(), point)))
+            .map(|(label, (date, value))| {
+                format!(
+                    "Seneste værdi for {} er {} ({}).",
+                    label,
+                    format_number(*value, decimals),
+                    date.format("%Y-%m")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+
+    // Goal series start after the last observation, which is where the forecast begins.
+    let latest = series
+        .annotations()
+        .iter()
+        .find_map(|a| match a {
+            Annotation::Forecast { from, .. } => Some(*from),
+            _ => None,
+        })
+        .or_else(|| series.xs().get_max().cloned());
+
+    let latest = match latest {
+        Some(date) => date,
+        None => return "Ingen data.".to_string(),
+    };
+    let value: f64 = series
+        .series()
+        .iter()
+        .filter_map(|ts| ts.data.get(&latest))
+        .sum();
+
+    let mut sentences = vec![format!(
+        "Seneste værdi er {} ({}).",
+        format_number(value, decimals),
+        latest.format("%Y-%m")
+    )];
+    for annotation in series.annotations() {
+        if let Annotation::Goal {
+            label,
+            value: goal,
+            date,
+        } = annotation
+        {
+            sentences.push(format!(
+                "{}: {} i {}, en ændring på {} fra seneste værdi.",
+                label,
+                format_number(*goal, decimals),
+                date.format("%Y"),
+                format_number(goal - value, decimals)
+            ));
+        }
+    }
+    sentences.join(" ")
+}
+
+/// A collapsible table with the same numbers as the chart, for screen readers and auditors.
+pub fn html(config: &ChartConfig, caption: String, decimals: u64) -> impl RenderOnce {
+    let x = config.options.scales.x_axes[0]
+        .scale_label
+        .label_string
+        .clone();
+    let labels = config.data.labels.clone();
+    let columns: Vec<String> = config
+        .data
+        .datasets
+        .iter()
+        .map(|ds| ds.label.clone())
+        .collect();
+    let rows: Vec<(String, Vec<String>)> = labels
+        .into_iter()
+        .enumerate()
+        .map(|(i, label)| {
+            let cells = config
+                .data
+                .datasets
+                .iter()
+                .map(|ds| {
+                    ds.data[i]
+                        .map(|v| format_number(v, decimals))
+                        .unwrap_or_default()
+                })
+                .collect();
+            (label, cells)
+        })
+        .collect();
+
+    owned_html! {
+        details {
+            summary { : "Vis data som tabel" }
+            div(class="table-responsive") {
+                table(class="table table-sm table-striped text-right") {
+                    caption { : caption }
+                    thead {
+                        tr {
+                            th(scope="col", class="text-left") { : x }
+                            @ for column in columns {
+                                th(scope="col") { : column }
+                            }
+                        }
+                    }
+                    tbody {
+                        @ for (label, cells) in rows {
+                            tr {
+                                th(scope="row", class="text-left") { : label }
+                                @ for cell in cells {
+                                    td { : cell }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::table::{TimeSeries, TimeSeriesGroup};
+    use crate::web::ChartGraph;
+    use chrono::NaiveDate;
+    use horrorshow::Template;
+
+    #[test]
+    fn test_summary_and_table() {
+        let data = (1..=3)
+            .map(|m| {
+                (
+                    NaiveDate::from_ymd_opt(2020, m, 1).unwrap(),
+                    m as f64 * 1500.0,
+                )
+            })
+            .collect();
+        let series = TimeSeriesGroup::new(
+            chrono::Utc::now(),
+            vec![TimeSeries::new(im::OrdSet::unit("El".to_string()), data)],
+        )
+        .future_goal(
+            "Mål",
+            NaiveDate::from_yo_opt(2021, 1).unwrap(),
+            10_000.0,
+            chrono::Duration::days(31),
+        )
+        .unwrap();
+
+        assert_eq!(
+            super::summary(&series),
+            "Seneste værdi er 4.500 (2020-03). Mål: 10.000 i 2021, en ændring på 5.500 fra seneste værdi."
+        );
+
+        let graph = ChartGraph::bar_plot(
+            "ev".into(),
+            "Elbiler".into(),
+            "måned".into(),
+            "biler".into(),
+            series,
+        );
+        let html = super::html(&graph.config, "Elbiler (biler)".into(), 0)
+            .into_string()
+            .unwrap();
+        assert!(html.contains("<caption>Elbiler (biler)</caption>"));
+        assert!(html
+            .contains("<th scope=\"row\" class=\"text-left\">2020-02</th><td>3.000</td><td></td>"));
+    }
+}
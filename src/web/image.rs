@@ -0,0 +1,400 @@
+use std::fmt::Write;
+use std::sync::{Arc, OnceLock};
+
+use resvg::{tiny_skia, usvg};
+
+use super::{format_number, ChartAnnotation, ChartConfig};
+
+const WIDTH: f64 = 960.0;
+const HEIGHT: f64 = 480.0;
+const LEFT: f64 = 90.0;
+const RIGHT: f64 = 20.0;
+const TOP: f64 = 40.0;
+const BOTTOM: f64 = 110.0;
+const FONT: &str = "font-family=\"Helvetica, Arial, DejaVu Sans, sans-serif\" font-size=\"12\"";
+const TITLE_FONT: &str =
+    "font-family=\"Helvetica, Arial, DejaVu Sans, sans-serif\" font-size=\"16\"";
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Rounds the value range up to a readable tick step of 1, 2 or 5 times a power of ten.
+fn tick_step(range: f64) -> f64 {
+    let raw = (range / 5.0).max(1.0);
+    let magnitude = 10f64.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= raw)
+        .unwrap()
+}
+
+/// Stacked cumulative values per dataset, mirroring Chart.js' stacked line charts.
+/// Unstacked charts get a zero-height band per point, so only their lines are drawn.
+fn stacked(config: &ChartConfig) -> Vec<Vec<Option<(f64, f64)>>> {
+    if !is_stacked(config) {
+        return config
+            .data
+            .datasets
+            .iter()
+            .map(|ds| ds.data.iter().map(|v| v.map(|v| (v, v))).collect())
+            .collect();
+    }
+
+    let mut base = vec![0f64; config.data.labels.len()];
+    config
+        .data
+        .datasets
+        .iter()
+        .map(|ds| {
+            ds.data
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    v.map(|v| {
+                        let low = base[i];
+                        base[i] += v;
+                        (low, base[i])
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn is_stacked(config: &ChartConfig) -> bool {
+    config.options.scales.y_axes.iter().any(|axis| axis.stacked)
+}
+
+/// Renders a chart to a static SVG document without any JavaScript.
+pub fn svg(config: &ChartConfig) -> String {
+    let labels = &config.data.labels;
+    let stacks = stacked(config);
+
+    let goals = config
+        .options
+        .annotation
+        .annotations
+        .iter()
+        .filter_map(|a| match a {
+            ChartAnnotation::Line { mode, value, .. } if mode == "horizontal" => value.as_f64(),
+            _ => None,
+        });
+    let values = stacks
+        .iter()
+        .flatten()
+        .flatten()
+        .flat_map(|(l, h)| vec![*l, *h]);
+    let (min, max) = values
+        .chain(goals)
+        .fold((0f64, 0f64), |(lo, hi), v| (lo.min(v), hi.max(v)));
+
+    let step = tick_step(max - min);
+    let y_min = (min / step).floor() * step;
+    let y_max = ((max / step).ceil() * step).max(y_min + step);
+
+    let plot_w = WIDTH - LEFT - RIGHT;
+    let plot_h = HEIGHT - TOP - BOTTOM;
+    let x = |i: usize| LEFT + plot_w * i as f64 / (labels.len().max(2) - 1) as f64;
+    let y = |v: f64| TOP + plot_h * (y_max - v) / (y_max - y_min);
+    let x_of = |label: &str| labels.iter().position(|l| l == label).map(x);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = WIDTH,
+        h = HEIGHT
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "<rect width=\"100%\" height=\"100%\" fill=\"#ffffff\"/>"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "<text x=\"{}\" y=\"24\" text-anchor=\"middle\" {}>{}</text>",
+        WIDTH / 2.0,
+        TITLE_FONT,
+        escape(&config.options.title.text)
+    )
+    .unwrap();
+
+    // Forecast regions go below the data, like the plugin's default draw order for boxes.
+    for annotation in &config.options.annotation.annotations {
+        if let ChartAnnotation::Box {
+            x_min,
+            x_max,
+            background_color,
+            ..
+        } = annotation
+        {
+            if let (Some(x0), Some(x1)) = (x_of(x_min), x_of(x_max)) {
+                writeln!(
+                    out,
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>",
+                    x0,
+                    TOP,
+                    x1 - x0,
+                    plot_h,
+                    background_color
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    // Ticks show as many decimals as their step needs, e.g. none for steps of 5 and one for 0.5.
+    let decimals = (-step.log10().floor()).max(0.0) as u64;
+    // Ticks are counted rather than accumulated, so rounding never drops the top one.
+    let ticks = ((y_max - y_min) / step).round() as usize;
+    for tick in (0..=ticks).map(|i| y_min + i as f64 * step) {
+        writeln!(
+            out,
+            "<line x1=\"{l}\" x2=\"{r}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"#e5e5e5\"/>\
+             <text x=\"{t}\" y=\"{y:.1}\" dy=\"4\" text-anchor=\"end\" {f}>{v}</text>",
+            l = LEFT,
+            r = WIDTH - RIGHT,
+            y = y(tick),
+            t = LEFT - 6.0,
+            f = FONT,
+            v = format_number(tick, decimals)
+        )
+        .unwrap();
+    }
+
+    let every = (labels.len() / 12).max(1);
+    for (i, label) in labels.iter().enumerate().step_by(every) {
+        writeln!(
+            out,
+            "<text x=\"{x:.1}\" y=\"{y}\" text-anchor=\"end\" transform=\"rotate(-45 {x:.1} {y})\" {f}>{l}</text>",
+            x = x(i),
+            y = HEIGHT - BOTTOM + 16.0,
+            f = FONT,
+            l = escape(label)
+        )
+        .unwrap();
+    }
+
+    for (ds, stack) in config.data.datasets.iter().zip(&stacks) {
+        let runs = stack.iter().enumerate().fold(
+            vec![vec![]],
+            |mut runs: Vec<Vec<(usize, f64, f64)>>, (i, p)| {
+                match p {
+                    Some((low, high)) => runs.last_mut().unwrap().push((i, *low, *high)),
+                    None => runs.push(vec![]),
+                }
+                runs
+            },
+        );
+        for run in runs.iter().filter(|r| !r.is_empty()) {
+            let top: Vec<String> = run
+                .iter()
+                .map(|(i, _, high)| format!("{:.1},{:.1}", x(*i), y(*high)))
+                .collect();
+            let bottom: Vec<String> = run
+                .iter()
+                .rev()
+                .map(|(i, low, _)| format!("{:.1},{:.1}", x(*i), y(*low)))
+                .collect();
+            if is_stacked(config) {
+                writeln!(
+                    out,
+                    "<polygon points=\"{} {}\" fill=\"{}\" fill-opacity=\"0.8\"/>",
+                    top.join(" "),
+                    bottom.join(" "),
+                    ds.background_color,
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>",
+                top.join(" "),
+                ds.border_color,
+                ds.border_width
+            )
+            .unwrap();
+        }
+        if ds.point_radius > 0 {
+            for (i, (_, high)) in stack
+                .iter()
+                .enumerate()
+                .filter_map(|(i, p)| Some((i, (*p)?)))
+            {
+                writeln!(
+                    out,
+                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" fill=\"{}\"/>",
+                    x(i),
+                    y(high),
+                    ds.point_radius,
+                    ds.background_color
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    writeln!(
+        out,
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#999999\"/>",
+        LEFT, TOP, plot_w, plot_h
+    )
+    .unwrap();
+
+    for annotation in &config.options.annotation.annotations {
+        if let ChartAnnotation::Line {
+            mode,
+            value,
+            border_color,
+            border_width,
+            border_dash,
+            label,
+            ..
+        } = annotation
+        {
+            let dash = border_dash
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            let line = match (mode.as_str(), value) {
+                ("horizontal", v) => v.as_f64().map(|v| (LEFT, y(v), WIDTH - RIGHT, y(v))),
+                (_, serde_json::Value::String(s)) => x_of(s).map(|x| (x, TOP, x, TOP + plot_h)),
+                _ => None,
+            };
+            if let Some((x1, y1, x2, y2)) = line {
+                writeln!(
+                    out,
+                    "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"{}\" stroke-dasharray=\"{}\"/>\
+                     <text x=\"{:.1}\" y=\"{:.1}\" dx=\"4\" dy=\"-4\" fill=\"{}\" {}>{}</text>",
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    border_color,
+                    border_width,
+                    dash,
+                    x1,
+                    if mode == "horizontal" { y1 } else { TOP + 14.0 },
+                    border_color,
+                    FONT,
+                    escape(&label.content)
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    let y_label = &config.options.scales.y_axes[0].scale_label.label_string;
+    writeln!(
+        out,
+        "<text x=\"16\" y=\"{y:.1}\" text-anchor=\"middle\" transform=\"rotate(-90 16 {y:.1})\" {f}>{l}</text>",
+        y = TOP + plot_h / 2.0,
+        f = FONT,
+        l = escape(y_label)
+    )
+    .unwrap();
+
+    for (n, ds) in config.data.datasets.iter().enumerate() {
+        let legend_y = HEIGHT - 34.0 + 16.0 * (n / 2) as f64;
+        let legend_x = LEFT + (n % 2) as f64 * plot_w / 2.0;
+        writeln!(
+            out,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"12\" height=\"12\" fill=\"{}\"/>\
+             <text x=\"{:.1}\" y=\"{:.1}\" {}>{}</text>",
+            legend_x,
+            legend_y - 10.0,
+            ds.background_color,
+            legend_x + 18.0,
+            legend_y,
+            FONT,
+            escape(&ds.label)
+        )
+        .unwrap();
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+/// The system fonts, loaded once for all images since scanning them is slow.
+fn fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut fonts = usvg::fontdb::Database::new();
+            fonts.load_system_fonts();
+            Arc::new(fonts)
+        })
+        .clone()
+}
+
+/// Rasterizes an SVG document, using whatever fonts the system provides for the labels.
+pub fn png(svg: &str) -> Result<Vec<u8>, failure::Error> {
+    let options = usvg::Options {
+        fontdb: fonts(),
+        ..usvg::Options::default()
+    };
+
+    let tree = usvg::Tree::from_str(svg, &options)?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| failure::format_err!("invalid image size: {:?}", size))?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+
+    Ok(pixmap.encode_png()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::table::{TimeSeries, TimeSeriesGroup};
+    use crate::web::ChartGraph;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_svg_and_png() {
+        let data = (1..=12)
+            .map(|m| {
+                (
+                    NaiveDate::from_ymd_opt(2020, m, 1).unwrap(),
+                    m as f64 * 100.0,
+                )
+            })
+            .collect();
+        let series = TimeSeriesGroup::new(
+            chrono::Utc::now(),
+            vec![TimeSeries::new(im::OrdSet::unit("El".to_string()), data)],
+        )
+        .future_goal(
+            "Mål",
+            NaiveDate::from_yo_opt(2022, 1).unwrap(),
+            5000.0,
+            chrono::Duration::days(31),
+        )
+        .unwrap();
+        let graph = ChartGraph::bar_plot(
+            "ev".into(),
+            "Elbiler & mål".into(),
+            "måned".into(),
+            "biler".into(),
+            series,
+        );
+
+        let svg = super::svg(&graph.config);
+        assert!(svg.contains("Elbiler &amp; mål"));
+        assert!(svg.contains(">2020-01<"));
+        // Ticks of 1.000 from zero up to and including the goal.
+        assert!((0..=5)
+            .all(|i| svg.contains(&format!(">{}<", super::format_number(i as f64 * 1000.0, 0)))));
+        assert_eq!(svg.matches("<polygon").count(), 2);
+
+        let png = super::png(&svg).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
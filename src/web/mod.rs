@@ -1,9 +1,16 @@
-use colorous;
+pub mod assets;
+mod datatable;
+mod image;
+
 use serde::{Deserialize, Serialize};
-use serde_json;
 
-use crate::table::TimeSeriesGroup;
+use crate::table::{Annotation, Footnote, Provenance, TimeSeriesGroup};
+use chrono::NaiveDate;
 use horrorshow::prelude::*;
+use std::path::PathBuf;
+
+const X_AXIS: &str = "x-axis-0";
+const Y_AXIS: &str = "y-axis-0";
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -11,8 +18,8 @@ struct ChartDataSet {
     label: String,
     background_color: String,
     border_color: String,
-    data: Vec<Option<i64>>,
-    fill: String,
+    data: Vec<Option<f64>>,
+    fill: serde_json::Value,
     border_width: u64,
     point_radius: u64,
     point_hover_radius: u64,
@@ -52,6 +59,7 @@ struct ChartScaleLabel {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ChartScale {
+    id: String,
     stacked: bool,
     display: bool,
     scale_label: ChartScaleLabel,
@@ -64,6 +72,46 @@ struct ChartScales {
     y_axes: Vec<ChartScale>,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChartAnnotationLabel {
+    enabled: bool,
+    content: String,
+    position: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ChartAnnotation {
+    #[serde(rename_all = "camelCase")]
+    Line {
+        mode: String,
+        #[serde(rename = "scaleID")]
+        scale_id: String,
+        value: serde_json::Value,
+        border_color: String,
+        border_width: u64,
+        border_dash: Vec<u64>,
+        label: ChartAnnotationLabel,
+    },
+    #[serde(rename_all = "camelCase")]
+    Box {
+        #[serde(rename = "xScaleID")]
+        x_scale_id: String,
+        x_min: String,
+        x_max: String,
+        background_color: String,
+        border_width: u64,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChartAnnotations {
+    draw_time: String,
+    annotations: Vec<ChartAnnotation>,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct ChartOptions {
     responsive: bool,
@@ -71,6 +119,7 @@ struct ChartOptions {
     tooltips: ChartToolTips,
     hover: ChartHover,
     scales: ChartScales,
+    annotation: ChartAnnotations,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -86,6 +135,163 @@ pub struct ChartGraph {
     config: ChartConfig,
 }
 
+/// Where and how the generated page is written, shared by every chart on it.
+#[derive(Clone, Debug, Default)]
+pub struct Site {
+    /// Directory for the page and its companion files; the page goes to stdout when unset.
+    pub dir: Option<PathBuf>,
+    /// Render each chart to SVG and PNG and embed them for readers without JavaScript.
+    pub fallback_images: bool,
+    /// Serve Bootstrap, jQuery and Chart.js from the output directory instead of CDNs.
+    pub offline: bool,
+    /// Directory with previously downloaded assets, so offline builds need no network.
+    pub asset_cache: Option<PathBuf>,
+    /// Fail rather than warn when a chart would leave out a mandatory footnote.
+    pub strict: bool,
+}
+
+impl Site {
+    pub fn write(&self, name: &str, contents: &[u8]) -> Result<(), failure::Error> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or_else(|| failure::format_err!("no output directory for {}", name))?;
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// Formats a number the Danish way with the given decimals, e.g. 1.000.000 or 74,3.
+fn format_number(value: f64, decimals: u64) -> String {
+    let formatted = format!("{:.*}", decimals as usize, value.abs());
+    let (digits, fraction) = match formatted.split_once('.') {
+        Some((digits, fraction)) => (digits, Some(fraction)),
+        None => (formatted.as_str(), None),
+    };
+    let grouped = digits
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(|c| std::str::from_utf8(c).unwrap())
+        .collect::<Vec<_>>()
+        .join(".");
+    let number = match fraction {
+        Some(fraction) => format!("{},{}", grouped, fraction),
+        None => grouped,
+    };
+    if value < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        format!("-{}", number)
+    } else {
+        number
+    }
+}
+
+/// The source line under a chart: the source with its link, then unit, selection and fetch date.
+fn citation(provenance: &Provenance) -> (String, Option<String>, String) {
+    let name = [&provenance.source, &provenance.table, &provenance.title]
+        .iter()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut details = vec![];
+    if !provenance.unit.is_empty() {
+        details.push(format!("Enhed: {}.", provenance.unit.trim_end_matches('.')));
+    }
+    if !provenance.selection.is_empty() {
+        let selection = provenance
+            .selection
+            .iter()
+            .map(|(variable, values)| format!("{}: {}", variable, values.join(", ")))
+            .collect::<Vec<_>>()
+            .join("; ");
+        details.push(format!("Udvalg: {}.", selection));
+    }
+    details.push(format!("Hentet {}.", provenance.fetched.format("%d-%m-%Y")));
+
+    (
+        name,
+        provenance.documentation.clone(),
+        format!(". {}", details.join(" ")),
+    )
+}
+
+/// The footnotes of all cited sources to show under a chart. Mandatory footnotes are shown even
+/// when the chart hides footnotes, as StatBank's terms require, and fail the build in strict mode.
+fn footnotes<'a>(
+    site: &Site,
+    id: &str,
+    series: &'a TimeSeriesGroup,
+) -> Result<Vec<&'a Footnote>, failure::Error> {
+    let footnotes: Vec<&Footnote> = series.citations().flat_map(|p| &p.footnotes).collect();
+    if !series.footnotes_hidden() {
+        return Ok(footnotes);
+    }
+    for footnote in footnotes.iter().filter(|f| f.mandatory) {
+        if site.strict {
+            failure::bail!("{} hides the mandatory footnote \"{}\"", id, footnote.text);
+        }
+        eprintln!(
+            "{}: showing the mandatory footnote despite hidden footnotes: {}",
+            id, footnote.text
+        );
+    }
+    Ok(footnotes.into_iter().filter(|f| f.mandatory).collect())
+}
+
+/// The x-axis is categorical, so dates are placed on the last label at or before them.
+fn x_label(xs: &im::OrdSet<NaiveDate>, date: NaiveDate) -> String {
+    xs.range(..=date)
+        .next_back()
+        .or_else(|| xs.get_min())
+        .unwrap_or(&date)
+        .format("%Y-%m")
+        .to_string()
+}
+
+fn chart_annotation(xs: &im::OrdSet<NaiveDate>, annotation: &Annotation) -> ChartAnnotation {
+    let label = |content: &str, position: &str| ChartAnnotationLabel {
+        enabled: true,
+        content: content.to_string(),
+        position: position.to_string(),
+    };
+
+    match annotation {
+        Annotation::Goal {
+            label: text, value, ..
+        } => ChartAnnotation::Line {
+            mode: "horizontal".to_string(),
+            scale_id: Y_AXIS.to_string(),
+            value: (*value).into(),
+            border_color: "#dc3545".to_string(),
+            border_width: 1,
+            border_dash: vec![6, 3],
+            label: label(text, "left"),
+        },
+        Annotation::Event { label: text, date } => ChartAnnotation::Line {
+            mode: "vertical".to_string(),
+            scale_id: X_AXIS.to_string(),
+            value: x_label(xs, *date).into(),
+            border_color: "#6c757d".to_string(),
+            border_width: 1,
+            border_dash: vec![],
+            label: label(text, "top"),
+        },
+        Annotation::Forecast { from, to } => ChartAnnotation::Box {
+            x_scale_id: X_AXIS.to_string(),
+            x_min: x_label(xs, *from),
+            x_max: x_label(xs, *to),
+            background_color: "rgba(108, 117, 125, 0.1)".to_string(),
+            border_width: 0,
+        },
+    }
+}
+
 impl ChartGraph {
     pub fn bar_plot(
         id: String,
@@ -104,18 +310,18 @@ impl ChartGraph {
             .map(|(n, ts)| {
                 let color = colors.eval_rational(n, series.len());
                 ChartDataSet {
-                    label: ts
-                        .tags
-                        .iter()
-                        .map(|d| d.as_str())
-                        .collect::<Vec<_>>()
-                        .join(","),
+                    label: ts.label(),
                     background_color: format!("#{:x}", color),
                     border_color: format!("#{:x}", color),
                     data: xs.iter().map(|x| ts.data.get(x).cloned()).collect(),
-                    fill: "start".to_string(),
-                    border_width: 1,
-                    point_radius: 0,
+                    fill: if series.stacked() {
+                        "start".into()
+                    } else {
+                        false.into()
+                    },
+                    border_width: if series.stacked() { 1 } else { 2 },
+                    // Overlays are often single targets, which only show as points.
+                    point_radius: if series.is_overlay(ts) { 3 } else { 0 },
                     point_hover_radius: 1,
                 }
             })
@@ -137,7 +343,8 @@ impl ChartGraph {
             },
             scales: ChartScales {
                 x_axes: vec![ChartScale {
-                    stacked: true,
+                    id: X_AXIS.to_string(),
+                    stacked: series.stacked(),
                     display: true,
                     scale_label: ChartScaleLabel {
                         display: false,
@@ -145,7 +352,8 @@ impl ChartGraph {
                     },
                 }],
                 y_axes: vec![ChartScale {
-                    stacked: true,
+                    id: Y_AXIS.to_string(),
+                    stacked: series.stacked(),
                     display: true,
                     scale_label: ChartScaleLabel {
                         display: true,
@@ -153,6 +361,14 @@ impl ChartGraph {
                     },
                 }],
             },
+            annotation: ChartAnnotations {
+                draw_time: "afterDatasetsDraw".to_string(),
+                annotations: series
+                    .annotations()
+                    .iter()
+                    .map(|a| chart_annotation(&xs, a))
+                    .collect(),
+            },
         };
 
         let config = ChartConfig {
@@ -168,13 +384,73 @@ impl ChartGraph {
     }
 
     pub fn bar_plot_html(
+        site: &Site,
         id: String,
         title: String,
         x: String,
         y: String,
         series: TimeSeriesGroup,
-    ) -> impl horrorshow::RenderOnce {
-        let graph = Self::bar_plot(id.clone(), title, x, y, series);
+    ) -> Result<impl horrorshow::RenderOnce, failure::Error> {
+        let updated = series.updated().naive_utc();
+        let stale = series.stale_since().map(|fetched| {
+            format!(
+                "Data fra {}: kilden kunne ikke nås, så tallene er måske ikke de nyeste.",
+                fetched.format("%d-%m-%Y")
+            )
+        });
+        let citations: Vec<_> = series.citations().map(citation).collect();
+        let footnotes: Vec<String> = footnotes(site, &id, &series)?
+            .into_iter()
+            .map(|f| f.text.clone())
+            .collect();
+        let summary = datatable::summary(&series);
+        let decimals = series.decimals();
+        let y = match series.unit_label() {
+            Some(unit) => format!("{}, {}", y, unit),
+            None => y,
+        };
+        let label = format!("{}. {}", title, summary);
+        let caption = format!("{} ({}), opdateret {}", title, y, updated);
+
+        let downloads: Vec<(String, &str)> = if site.dir.is_some() {
+            let exports = vec![
+                (format!("{}.csv", id), "CSV", series.to_csv_long()),
+                (
+                    format!("{}.wide.csv", id),
+                    "CSV (bred)",
+                    series.to_csv_wide(),
+                ),
+                (
+                    format!("{}.da.csv", id),
+                    "CSV (dansk Excel)",
+                    series.to_csv_danish(),
+                ),
+                (format!("{}.json", id), "JSON", series.to_json()),
+            ];
+            for (file, _, contents) in &exports {
+                site.write(file, contents.as_bytes())?;
+            }
+            exports
+                .into_iter()
+                .map(|(file, name, _)| (file, name))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let y = format!("{} — {}", y, updated);
+        let graph = Self::bar_plot(id.clone(), title.clone(), x, y, series);
+        let table = datatable::html(&graph.config, caption, decimals);
+
+        let images = if site.fallback_images {
+            let svg = image::svg(&graph.config);
+            site.write(&format!("{}.png", id), &image::png(&svg)?)?;
+            site.write(&format!("{}.svg", id), svg.as_bytes())?;
+            Some((format!("{}.svg", id), format!("{}.png", id)))
+        } else {
+            None
+        };
+
         let json = serde_json::to_string_pretty(&graph.config).unwrap();
 
         let js = format!(
@@ -187,11 +463,174 @@ window.addEventListener(\"load\", function () {{
             json, graph.name, graph.name
         );
 
-        html! {
-            canvas(id=id) {}
+        Ok(html! {
+            @ if let Some(stale) = stale {
+              div(class="alert alert-warning", role="alert") { : stale }
+            }
+            canvas(id=id, role="img", aria-label=label) {}
             script {
               : Raw(js)
             }
-        }
+            @ if let Some((svg, png)) = images {
+              noscript {
+                picture {
+                  source(srcset=svg, type="image/svg+xml") {}
+                  img(src=png, alt=title, class="img-fluid") {}
+                }
+              }
+            }
+            p(class="small text-muted") { : summary }
+            @ for (name, url, details) in citations {
+              p(class="small text-muted") {
+                : "Kilde: ";
+                @ if let Some(url) = url {
+                  a(href=url, target="_blank") { : name }
+                } else {
+                  : name
+                }
+                : details
+              }
+            }
+            @ for footnote in footnotes {
+              p(class="small text-muted") { : format!("Fodnote: {}", footnote) }
+            }
+            @ if !downloads.is_empty() {
+              p(class="small") {
+                : "Hent data: ";
+                @ for (n, (file, name)) in downloads.into_iter().enumerate() {
+                  @ if n > 0 {
+                    : " · "
+                  }
+                  a(href=file.clone(), download=file) { : name }
+                }
+              }
+            }
+            : table
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChartGraph, Site};
+    use crate::golden::assert_golden;
+    use crate::table::{Footnote, Provenance, TimeSeries, TimeSeriesGroup};
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use horrorshow::Template;
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(super::format_number(1_000_000.0, 0), "1.000.000");
+        assert_eq!(super::format_number(74.34, 1), "74,3");
+        assert_eq!(super::format_number(-1234.5, 2), "-1.234,50");
+        assert_eq!(super::format_number(-0.04, 1), "0,0");
+    }
+
+    #[test]
+    fn test_bar_plot_config() {
+        let date = |m| NaiveDate::from_ymd_opt(2020, m, 1).unwrap();
+        let series = |tag: &str, values: &[f64]| {
+            TimeSeries::new(
+                im::OrdSet::unit(tag.to_string()),
+                (1..).map(date).zip(values.iter().cloned()).collect(),
+            )
+        };
+        let group = TimeSeriesGroup::new(
+            Utc.with_ymd_and_hms(2020, 10, 8, 6, 0, 0).unwrap(),
+            vec![
+                series("Benzin", &[100.0, 110.0, 120.0]),
+                series("Diesel", &[50.0, 45.0, 40.0]),
+            ],
+        )
+        .event("Klimaloven vedtaget", date(2))
+        .future_goal("Vej til mål", date(6), 0.0, chrono::Duration::days(31))
+        .unwrap();
+
+        let graph = ChartGraph::bar_plot(
+            "cars".into(),
+            "Nye biler".into(),
+            "måned".into(),
+            "antal".into(),
+            group,
+        );
+        assert_golden(
+            "cars.config.json",
+            &serde_json::to_string_pretty(&graph.config).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let updated = Utc.with_ymd_and_hms(2020, 10, 8, 6, 0, 0).unwrap();
+        let footnote = |text: &str, mandatory| Footnote {
+            text: text.to_string(),
+            mandatory,
+        };
+        let statbank = |tag: &str, footnotes| {
+            TimeSeriesGroup::new(
+                updated,
+                vec![TimeSeries::unit(
+                    im::OrdSet::unit(tag.to_string()),
+                    NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+                    100.0,
+                )],
+            )
+            .with_provenance(Provenance {
+                source: "Danmarks Statistik".to_string(),
+                table: "BIL51".to_string(),
+                title: "Nyregistrerede personbiler".to_string(),
+                unit: "antal".to_string(),
+                documentation: None,
+                selection: Default::default(),
+                fetched: updated,
+                footnotes,
+            })
+        };
+        let group = || {
+            statbank(
+                "Benzin",
+                vec![footnote("Skal med", true), footnote("Kan udelades", false)],
+            )
+        };
+        let render = |site: &Site, group| {
+            ChartGraph::bar_plot_html(
+                site,
+                "cars".into(),
+                "Nye biler".into(),
+                "måned".into(),
+                "antal".into(),
+                group,
+            )
+            .map(|html| html! { : html }.into_string().unwrap())
+        };
+
+        let site = Site::default();
+        let html = render(&site, group()).unwrap();
+        assert!(html.contains("Fodnote: Skal med"));
+        assert!(html.contains("Fodnote: Kan udelades"));
+
+        let html = render(&site, group().without_footnotes()).unwrap();
+        assert!(html.contains("Fodnote: Skal med"));
+        assert!(!html.contains("Kan udelades"));
+
+        let strict = Site {
+            strict: true,
+            ..Site::default()
+        };
+        assert!(render(&strict, group()).is_ok());
+        let error = render(&strict, group().without_footnotes()).err().unwrap();
+        assert!(error.to_string().contains("Skal med"));
+
+        // The footnotes of an overlaid source count as well.
+        let overlaid = || {
+            statbank("Diesel", vec![])
+                .overlay(group())
+                .without_footnotes()
+        };
+        let html = render(&site, overlaid()).unwrap();
+        assert!(html.contains("Fodnote: Skal med"));
+        assert!(!html.contains("Kan udelades"));
+        let error = render(&strict, overlaid()).err().unwrap();
+        assert!(error.to_string().contains("Skal med"));
     }
 }